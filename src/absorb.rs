@@ -0,0 +1,278 @@
+use std::{collections::HashSet, io::Write, process::Stdio};
+
+use anyhow::{anyhow, Context, Result};
+use indexmap::IndexMap;
+
+use crate::git::{Repo, TrueOr};
+
+/// The staged changes of one file.
+#[derive(Debug)]
+struct FileDiff {
+    /// everything up to the first hunk
+    header: String,
+    path: String,
+    hunks: Vec<Hunk>,
+}
+
+/// A hunk of a diff without context lines.
+#[derive(Debug)]
+struct Hunk {
+    /// first line (of `HEAD`'s version) the hunk replaces, or that it adds lines after
+    old_start: usize,
+    /// number of lines the hunk replaces
+    old_lines: usize,
+    /// the hunk, including its `@@` line
+    text: String,
+}
+
+impl Hunk {
+    fn summary(&self, path: &str) -> String {
+        format!("{path}:{}", self.old_start.max(1))
+    }
+}
+
+/// Where a staged hunk would go.
+pub struct Target {
+    /// `file:line` of the hunk
+    pub hunk: String,
+    /// the commit that last touched the hunk's lines, if it is one of the candidates
+    pub commit: Option<String>,
+}
+
+/// Returns the staged changes that could be absorbed, without context lines.
+///
+/// New, deleted, renamed and binary files are left out.
+fn staged_changes(repo: &Repo) -> Result<Vec<FileDiff>> {
+    let diff = repo.cmd_output([
+        "diff",
+        "--cached",
+        "--unified=0",
+        "--no-color",
+        "--no-ext-diff",
+        "--no-renames",
+        "--src-prefix=a/",
+        "--dst-prefix=b/",
+    ])?;
+
+    let mut files = Vec::new();
+    for file in diff.split("\ndiff --git ").filter(|file| !file.is_empty()) {
+        let file = if file.starts_with("diff --git ") {
+            file.to_string()
+        } else {
+            format!("diff --git {file}")
+        };
+        let file = if file.ends_with('\n') {
+            file
+        } else {
+            file + "\n"
+        };
+
+        let (header, hunks) = match file.find("\n@@ ") {
+            Some(index) => file.split_at(index + 1),
+            None => continue,
+        };
+        if header.lines().any(|line| {
+            line.starts_with("new file mode")
+                || line.starts_with("deleted file mode")
+                || line.starts_with("Binary files")
+        }) {
+            continue;
+        }
+        let Some(path) = header
+            .lines()
+            .find_map(|line| line.strip_prefix("+++ b/"))
+            .map(str::to_string)
+        else {
+            continue;
+        };
+
+        let mut parsed = Vec::new();
+        for hunk in hunks.split("\n@@ ") {
+            let text = if hunk.starts_with("@@ ") {
+                hunk.to_string()
+            } else {
+                format!("@@ {hunk}")
+            };
+            let text = if text.ends_with('\n') {
+                text
+            } else {
+                text + "\n"
+            };
+            let (old_start, old_lines) =
+                parse_hunk_header(&text).ok_or_else(|| anyhow!("cannot parse diff of `{path}`"))?;
+            parsed.push(Hunk {
+                old_start,
+                old_lines,
+                text,
+            });
+        }
+
+        files.push(FileDiff {
+            header: header.to_string(),
+            path,
+            hunks: parsed,
+        });
+    }
+
+    Ok(files)
+}
+
+/// Parses the old line range of a hunk header (`@@ -12,3 +12,4 @@`).
+fn parse_hunk_header(text: &str) -> Option<(usize, usize)> {
+    let old = text.strip_prefix("@@ -")?.split(' ').next()?;
+    match old.split_once(',') {
+        Some((start, lines)) => Some((start.parse().ok()?, lines.parse().ok()?)),
+        None => Some((old.parse().ok()?, 1)),
+    }
+}
+
+/// Returns the commits that last touched lines `first` to `last` of `path`.
+fn blame_lines(repo: &Repo, path: &str, first: usize, last: usize) -> Result<HashSet<String>> {
+    let output = repo.cmd_output([
+        "blame",
+        "--porcelain",
+        "-L",
+        &format!("{first},{last}"),
+        "HEAD",
+        "--",
+        path,
+    ])?;
+
+    Ok(output
+        .lines()
+        .filter(|line| !line.starts_with('\t'))
+        .filter_map(|line| line.split(' ').next())
+        .filter(|word| word.len() == 40 && word.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Returns the commit that last touched the lines `hunk` changes, if that is
+/// a single one of `candidates`.
+///
+/// For hunks that only add lines, the line they get added after counts. If a
+/// later candidate touched the lines right next to the hunk, moving it down
+/// there would conflict, so it is left alone.
+fn blame(
+    repo: &Repo,
+    path: &str,
+    hunk: &Hunk,
+    candidates: &HashSet<String>,
+) -> Result<Option<String>> {
+    let first = hunk.old_start.max(1);
+    let last = first + hunk.old_lines.max(1) - 1;
+
+    let commits = blame_lines(repo, path, first, last)?;
+    let target = match commits.iter().next() {
+        Some(commit) if commits.len() == 1 && candidates.contains(commit) => commit.clone(),
+        _ => return Ok(None),
+    };
+
+    // blame fails beyond the end of the file, so look at each neighbour separately
+    for line in [first - 1, last + 1] {
+        if line == 0 {
+            continue;
+        }
+        for neighbour in blame_lines(repo, path, line, line).unwrap_or_default() {
+            if neighbour != target
+                && candidates.contains(&neighbour)
+                && repo.is_ancestor(&target, &neighbour)?
+            {
+                return Ok(None);
+            }
+        }
+    }
+
+    Ok(Some(target))
+}
+
+/// Returns where each staged hunk would be absorbed into.
+pub fn targets(repo: &Repo, candidates: &HashSet<String>) -> Result<Vec<Target>> {
+    let mut targets = Vec::new();
+    for file in staged_changes(repo)? {
+        for hunk in &file.hunks {
+            targets.push(Target {
+                hunk: hunk.summary(&file.path),
+                commit: blame(repo, &file.path, hunk, candidates)?,
+            });
+        }
+    }
+
+    Ok(targets)
+}
+
+/// Commits the staged hunks as fixups of the `candidates` that last touched
+/// them, returning the fixed up commits with their number of hunks.
+///
+/// Hunks that cannot be attributed to a single candidate stay staged.
+pub fn create_fixups(repo: &Repo, candidates: &HashSet<String>) -> Result<IndexMap<String, usize>> {
+    let staged = repo.cmd_output(["write-tree"])?.trim().to_string();
+    let mut fixups = IndexMap::new();
+
+    // one commit per round, as each one changes the line numbers
+    loop {
+        let mut patch = String::new();
+        let mut target = None;
+        let mut count = 0;
+        for file in staged_changes(repo)? {
+            let mut hunks = String::new();
+            for hunk in &file.hunks {
+                let Some(commit) = blame(repo, &file.path, hunk, candidates)? else {
+                    continue;
+                };
+                if target.get_or_insert_with(|| commit.clone()) == &commit {
+                    hunks.push_str(&hunk.text);
+                    count += 1;
+                }
+            }
+            if !hunks.is_empty() {
+                patch.push_str(&file.header);
+                patch.push_str(&hunks);
+            }
+        }
+        let Some(target) = target else {
+            break;
+        };
+
+        let committed = commit_fixup(repo, &patch, &target);
+
+        // back to what was staged, minus what got committed (also if that
+        // failed, to not lose what the user staged)
+        repo.cmd_check(["read-tree", &staged])?
+            .true_or(anyhow!("restoring the index failed"))?;
+        committed?;
+        *fixups.entry(target).or_default() += count;
+    }
+
+    Ok(fixups)
+}
+
+/// Commits `patch` (on top of `HEAD`) as fixup of `target`, through the index.
+fn commit_fixup(repo: &Repo, patch: &str, target: &str) -> Result<()> {
+    repo.cmd_check(["read-tree", "HEAD"])?
+        .true_or(anyhow!("resetting the index failed"))?;
+    apply_cached(repo, patch)?;
+    repo.cmd_check([
+        "commit",
+        "--quiet",
+        "--no-verify",
+        &format!("--fixup={target}"),
+    ])?
+    .true_or(anyhow!("committing fixup for {target} failed"))
+}
+
+/// Applies `patch` to the index.
+fn apply_cached(repo: &Repo, patch: &str) -> Result<()> {
+    let mut child = repo
+        .git()
+        .args(["apply", "--cached", "--unidiff-zero", "-"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed to execute git")?;
+    child.stdin.take().unwrap().write_all(patch.as_bytes())?;
+
+    child
+        .wait()?
+        .success()
+        .true_or(anyhow!("applying staged hunks to the index failed"))
+}
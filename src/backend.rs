@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+
+use crate::{config::BackendKind, git::GitCommand, output::warning};
+
+/// The git operations giddy needs in its hot paths.
+///
+/// `Repo` goes through this for them, so they can be served by something
+/// faster than spawning `git` for each query. Select with
+/// `backend = "cli"` or `backend = "gix"` in the giddy config.
+pub trait GitBackend: std::fmt::Debug {
+    /// Returns the names and heads of all local branches, sorted by name.
+    fn branch_heads(&self) -> Result<Vec<(String, String)>>;
+
+    /// Returns the commit id `rev` resolves to.
+    fn branch_head(&self, rev: &str) -> Result<String>;
+
+    /// Returns the best common ancestor of `one` and `two`, if any.
+    fn merge_base(&self, one: &str, two: &str) -> Result<Option<String>>;
+
+    /// Returns whether `ancestor` is an ancestor of (or equal to) `commit`.
+    fn is_ancestor(&self, ancestor: &str, commit: &str) -> Result<bool>;
+
+    /// Rebases the commits of `branch` after `old` onto `new`, passing `flags`
+    /// on to `git rebase`, returning whether that succeeded.
+    fn rebase_onto(&self, new: &str, old: &str, branch: &str, flags: &[String]) -> Result<bool>;
+}
+
+/// Returns the backend of kind `kind` for the repository at `git_dir`.
+#[cfg_attr(not(feature = "gix"), expect(unused_variables))]
+pub fn from_config(kind: BackendKind, git_dir: &Utf8Path) -> Box<dyn GitBackend> {
+    match kind {
+        BackendKind::Cli => Box::new(CliBackend),
+        #[cfg(feature = "gix")]
+        BackendKind::Gix => match GixBackend::open(git_dir) {
+            Ok(backend) => Box::new(backend),
+            Err(e) => {
+                warning!("{e:#}, falling back to the `cli` backend");
+                Box::new(CliBackend)
+            }
+        },
+        #[cfg(not(feature = "gix"))]
+        BackendKind::Gix => {
+            warning!("giddy was built without the `gix` backend, using `cli`");
+            Box::new(CliBackend)
+        }
+    }
+}
+
+/// Runs the `git` executable for everything.
+#[derive(Debug)]
+pub struct CliBackend;
+
+impl CliBackend {
+    fn output(&self, args: &[&str]) -> Result<std::process::Output> {
+        GitCommand::new()
+            .args(args)
+            .output()
+            .context("failed to execute git")
+    }
+}
+
+impl GitBackend for CliBackend {
+    fn branch_heads(&self) -> Result<Vec<(String, String)>> {
+        let output = self.output(&[
+            "for-each-ref",
+            "--format=%(refname:lstrip=2) %(objectname)",
+            "refs/heads",
+        ])?;
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(name, head)| (name.to_string(), head.to_string()))
+            .collect())
+    }
+
+    fn branch_head(&self, rev: &str) -> Result<String> {
+        let output = self.output(&["rev-parse", "--verify", "--quiet", rev])?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("cannot resolve `{rev}`"));
+        }
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    }
+
+    fn merge_base(&self, one: &str, two: &str) -> Result<Option<String>> {
+        let output = self.output(&["merge-base", one, two])?;
+        // 1 means there is none, anything else is an error (e.g., a bad rev)
+        if !matches!(output.status.code(), Some(0 | 1)) {
+            return Err(anyhow::anyhow!(
+                "finding the merge base of `{one}` and `{two}` failed"
+            ));
+        }
+        let res = String::from_utf8(output.stdout)?.trim().to_string();
+
+        Ok((!res.is_empty()).then_some(res))
+    }
+
+    fn is_ancestor(&self, ancestor: &str, commit: &str) -> Result<bool> {
+        Ok(self
+            .output(&["merge-base", "--is-ancestor", ancestor, commit])?
+            .status
+            .success())
+    }
+
+    fn rebase_onto(&self, new: &str, old: &str, branch: &str, flags: &[String]) -> Result<bool> {
+        Ok(GitCommand::new()
+            .arg("rebase")
+            .args(flags)
+            .args(["--onto", new, old, branch])
+            .status()?
+            .success())
+    }
+}
+
+/// Answers queries in-process using `gix`, leaving rebases to the `git` executable.
+#[cfg(feature = "gix")]
+#[derive(Debug)]
+pub struct GixBackend {
+    repo: gix::Repository,
+}
+
+#[cfg(feature = "gix")]
+impl GixBackend {
+    pub fn open(git_dir: &Utf8Path) -> Result<Self> {
+        let repo = gix::open(git_dir).with_context(|| format!("opening `{git_dir}` with gix"))?;
+        Ok(Self { repo })
+    }
+
+    fn resolve(&self, rev: &str) -> Result<gix::ObjectId> {
+        Ok(self
+            .repo
+            .rev_parse_single(rev)
+            .with_context(|| format!("resolving `{rev}`"))?
+            .object()?
+            .peel_to_commit()?
+            .id)
+    }
+}
+
+#[cfg(feature = "gix")]
+impl GitBackend for GixBackend {
+    fn branch_heads(&self) -> Result<Vec<(String, String)>> {
+        let references = self.repo.references()?;
+        let mut heads = Vec::new();
+        for reference in references
+            .local_branches()
+            .map_err(|e| anyhow::anyhow!("listing branches: {e}"))?
+        {
+            let reference = reference.map_err(|e| anyhow::anyhow!("listing branches: {e}"))?;
+            let name = reference.name().shorten().to_string();
+            let head = reference
+                .into_fully_peeled_id()
+                .with_context(|| format!("resolving `{name}`"))?;
+            heads.push((name, head.to_string()));
+        }
+        heads.sort();
+
+        Ok(heads)
+    }
+
+    fn branch_head(&self, rev: &str) -> Result<String> {
+        Ok(self.resolve(rev)?.to_string())
+    }
+
+    fn merge_base(&self, one: &str, two: &str) -> Result<Option<String>> {
+        use gix::repository::merge_base::Error;
+
+        match self.repo.merge_base(self.resolve(one)?, self.resolve(two)?) {
+            Ok(base) => Ok(Some(base.to_string())),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn is_ancestor(&self, ancestor: &str, commit: &str) -> Result<bool> {
+        let ancestor_id = self.resolve(ancestor)?;
+        let commit_id = self.resolve(commit)?;
+        if ancestor_id == commit_id {
+            return Ok(true);
+        }
+
+        Ok(self
+            .merge_base(ancestor, commit)?
+            .is_some_and(|base| base == ancestor_id.to_string()))
+    }
+
+    fn rebase_onto(&self, new: &str, old: &str, branch: &str, flags: &[String]) -> Result<bool> {
+        CliBackend.rebase_onto(new, old, branch, flags)
+    }
+}
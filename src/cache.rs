@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+
+use crate::git::{read_from_file, write_to_file};
+
+/// Entries beyond this count get dropped, so the cache cannot grow unbounded.
+const MAX_ENTRIES: usize = 10000;
+
+/// On-disk cache for expensive git queries.
+///
+/// All keys are made of commit ids, so entries never need invalidation: once
+/// a ref moves, its entries just don't get hit anymore.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    fork_points: HashMap<String, Option<String>>,
+    needs_update: HashMap<String, bool>,
+    #[serde(default)]
+    squash_merged: HashMap<String, bool>,
+    #[serde(skip)]
+    path: Utf8PathBuf,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Cache {
+    pub fn load(path: Utf8PathBuf) -> Self {
+        let mut cache: Cache = read_from_file(&path).unwrap_or_default();
+        cache.path = path;
+        cache
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        if self.dirty {
+            // parallel updates save from several threads, and a half-written
+            // file must never be visible
+            let tmp = self.path.with_extension(format!(
+                "tmp.{}.{}",
+                std::process::id(),
+                rand::random::<u32>()
+            ));
+            let written =
+                write_to_file(&tmp, self).and_then(|()| Ok(std::fs::rename(&tmp, &self.path)?));
+            if written.is_err() {
+                std::fs::remove_file(&tmp).ok();
+            }
+            written?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    pub fn fork_point(&self, key: &str) -> Option<Option<String>> {
+        self.fork_points.get(key).cloned()
+    }
+
+    pub fn set_fork_point(&mut self, key: String, fork_point: Option<String>) {
+        if self.fork_points.len() >= MAX_ENTRIES {
+            self.fork_points.clear();
+        }
+        self.fork_points.insert(key, fork_point);
+        self.dirty = true;
+    }
+
+    pub fn needs_update(&self, key: &str) -> Option<bool> {
+        self.needs_update.get(key).copied()
+    }
+
+    pub fn set_needs_update(&mut self, key: String, needs_update: bool) {
+        if self.needs_update.len() >= MAX_ENTRIES {
+            self.needs_update.clear();
+        }
+        self.needs_update.insert(key, needs_update);
+        self.dirty = true;
+    }
+
+    pub fn squash_merged(&self, key: &str) -> Option<bool> {
+        self.squash_merged.get(key).copied()
+    }
+
+    pub fn set_squash_merged(&mut self, key: String, squash_merged: bool) {
+        if self.squash_merged.len() >= MAX_ENTRIES {
+            self.squash_merged.clear();
+        }
+        self.squash_merged.insert(key, squash_merged);
+        self.dirty = true;
+    }
+}
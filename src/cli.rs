@@ -1,37 +1,279 @@
+use anyhow::Result;
 use clap::{crate_version, Arg, ArgAction, Command};
-//use clap_complete::engine::{ArgValueCandidates, SubcommandCandidates};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+
+use giddy::git::Repo;
+
+/// Completion candidates for a new dependency of the current branch.
+///
+/// Leaves out branches that would create a cycle and, if `exclude_deps` is set,
+/// existing dependencies.
+fn dependency_candidates(exclude_deps: bool) -> Result<Vec<CompletionCandidate>> {
+    let repo = Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+    let graph = repo.graph()?;
+    let descendants = graph.get_descendants(current_branch.name())?;
+
+    let mut candidates = Vec::new();
+    for name in repo.branch_names()? {
+        if &name == current_branch.name()
+            || descendants.contains(&name)
+            || (exclude_deps && current_branch.state.deps.contains(&name))
+        {
+            continue;
+        }
+
+        let help = graph
+            .stack_position(&name, &default_branch)
+            .map(|(root, depth)| format!("stack: {root}, depth {depth}").into());
+        candidates.push(CompletionCandidate::new(name).help(help));
+    }
+
+    Ok(candidates)
+}
+
+/// Completion candidates for removing a dependency of the current branch.
+fn current_dependency_candidates() -> Result<Vec<CompletionCandidate>> {
+    let repo = Repo::open()?;
+    let current_branch = repo.branch_current()?;
+
+    Ok(current_branch
+        .state
+        .deps
+        .iter()
+        .map(CompletionCandidate::new)
+        .collect())
+}
+
+/// Completion candidates for any local branch.
+fn branch_candidates() -> Result<Vec<CompletionCandidate>> {
+    let repo = Repo::open()?;
+
+    Ok(repo
+        .branch_names()?
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect())
+}
+
+/// Format of the `--porcelain` records of `show` and `status`.
+pub const PORCELAIN_BRANCH_FORMAT: &str = "\
+print one tab-separated record per branch, in dependency order, in a format \
+that stays stable across releases. Fields, `-` meaning none:
+  1. branch name
+  2. base branch
+  3. `planned`, `needs-update` or `up-to-date`
+  4. `merged` if merged into the default branch
+  5. `dirty` if marked as needing an update
+  6. PR number
+  7. `current` if checked out
+Messages go to stderr.";
+
+fn porcelain_arg() -> Arg {
+    Arg::new("porcelain")
+        .help("print stable, tab-separated records for scripts")
+        .long_help(PORCELAIN_BRANCH_FORMAT)
+        .long("porcelain")
+        .conflicts_with("remote")
+        .action(ArgAction::SetTrue)
+}
+
+/// `--remote` of `share push` and `share pull`.
+fn remote_arg() -> Arg {
+    Arg::new("remote")
+        .help("remote to share through (default: where the default branch gets pushed)")
+        .long("remote")
+        .value_name("REMOTE")
+}
 
 pub fn clap() -> clap::Command {
     Command::new("giddy")
         .version(crate_version!())
         .author("Kaspar Schleiser <kaspar@schleiser.de>")
         .about("Tend your trees")
+        .after_help(
+            "Exit codes: 0 success, 1 error, 2 branches need an update (`update --check`), \
+             3 stopped on conflicts, 4 branch not tracked, 5 dependency cycle, \
+             6 `check` found problems",
+        )
         .infer_subcommands(true)
         .arg(
             Arg::new("verbose")
-                .help("be verbose (e.g., show command lines)")
+                .help("print the git commands giddy runs (twice: also their output)")
                 .short('v')
                 .long("verbose")
                 .global(true)
                 .action(ArgAction::Count),
         )
+        .arg(
+            Arg::new("log-format")
+                .help("format of the log that -v enables")
+                .long("log-format")
+                .global(true)
+                .value_parser(["text", "json"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::new("non-interactive")
+                .help("never prompt, fail instead (default when not on a terminal or when CI=true)")
+                .long("non-interactive")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("no-pager")
+                .help("do not pipe long output into a pager")
+                .long("no-pager")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("dry-run")
+                .help("print the git commands and state changes instead of performing them")
+                .short('n')
+                .long("dry-run")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("target-branch")
+                .help("work on BRANCH instead of the current branch (`add`, `base`, `check`, `del` and `show`)")
+                .long("branch")
+                .value_name("BRANCH")
+                .global(true)
+                .add(ArgValueCandidates::new(|| {
+                    branch_candidates().unwrap_or_default()
+                })),
+        )
         .arg(
             Arg::new("quiet")
-                .help("do not print giddy messages")
+                .help("do not print informational messages (twice: also warnings)")
                 .short('q')
                 .long("quiet")
                 .global(true)
-                .action(ArgAction::Count)
-                .hide(true), // (not really supported, yet)
+                .action(ArgAction::Count),
+        )
+        .subcommand(
+            Command::new("absorb")
+                .about("turn staged changes into fixups of the commits in the stack that last touched them, then restack"),
         )
         .subcommand(
             Command::new("add")
                 .about("add a dependency to this branch")
                 .arg(
                     Arg::new("dependency")
-                        .required(true)
-                        .help("branch to add as dependency of this branch")
-                        .num_args(1..),
+                        .help("branch (or PR, as `#123` or URL) to add as dependency of this branch (default: pick interactively)")
+                        .num_args(1..)
+                        .add(ArgValueCandidates::new(|| {
+                            dependency_candidates(true).unwrap_or_default()
+                        })),
+                ),
+        )
+        .subcommand(
+            Command::new("base")
+                .about("show the recorded base of this branch")
+                .subcommand(
+                    Command::new("set")
+                        .about("record BRANCH as base (and first dependency) of this branch, without rebasing it")
+                        .arg(
+                            Arg::new("branch")
+                                .help("the new base (or a PR, as `#123` or URL)")
+                                .required(true)
+                                .add(ArgValueCandidates::new(|| {
+                                    dependency_candidates(false).unwrap_or_default()
+                                })),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("amend")
+                .about("amend the last commit (or fix up an earlier one) of this branch, then restack its dependents")
+                .arg(
+                    Arg::new("all")
+                        .help("also commit all changes of tracked files, as `git commit --all`")
+                        .short('a')
+                        .long("all")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("message")
+                        .help("use MESSAGE as new commit message")
+                        .short('m')
+                        .long("message")
+                        .value_name("MESSAGE")
+                        .conflicts_with("fixup"),
+                )
+                .arg(
+                    Arg::new("fixup")
+                        .help("squash the changes into COMMIT instead of the last commit")
+                        .long("fixup")
+                        .value_name("COMMIT"),
+                ),
+        )
+        .subcommand(
+            Command::new("bundle")
+                .about("share the current stack as a git bundle, including giddy state")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("bundle all branches of the current stack")
+                        .arg(
+                            Arg::new("file")
+                                .required(true)
+                                .help("bundle file to write"),
+                        ),
+                )
+                .subcommand(
+                    Command::new("apply")
+                        .about("create branches and their state from a bundle")
+                        .arg(
+                            Arg::new("file")
+                                .required(true)
+                                .help("bundle file to read"),
+                        )
+                        .arg(
+                            Arg::new("overwrite")
+                                .help("also replace existing branches and their states")
+                                .long("overwrite")
+                                .action(ArgAction::SetTrue),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("check that the current branch is consistent and up to date, for CI (never prompts)")
+                .long_about(
+                    "check that the current branch (or the one given with `--branch`) is \
+                     consistent and up to date, for CI. Never prompts.\n\n\
+                     Prints one tab-separated line per check: its name, `ok`, `fail` or `skip`, \
+                     and details. The checks are `tracked`, `deps-exist`, `base-is-dep`, \
+                     `no-cycle`, `base-ancestor` and `up-to-date`. Exits with 6 if any failed.\n\n\
+                     Without giddy state of its own (as in a fresh CI checkout), the states \
+                     shared with `giddy share push` get fetched and used, and branches without \
+                     a local head are looked up on the remote. On a detached HEAD, the tracked \
+                     branch whose head is checked out gets checked.",
+                )
+                .arg(
+                    Arg::new("shared")
+                        .help("use the states shared with `giddy share push`, even if there are local ones")
+                        .long("shared")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(remote_arg().help(
+                    "remote to fetch shared states and branch heads from \
+                     (default: where the default branch gets pushed)",
+                )),
+        )
+        .subcommand(
+            Command::new("co")
+                .about("switch to a branch, picked from all tracked branches")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch (or PR, as `#123` or URL) to switch to (default: pick interactively)")
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
                 ),
         )
         .subcommand(
@@ -39,36 +281,567 @@ pub fn clap() -> clap::Command {
                 .about("remove a dependency from this branch")
                 .arg(
                     Arg::new("dependency")
+                        .help("branch to remove from the dependencies of this branch (default: pick interactively)")
+                        .num_args(1..)
+                        .add(ArgValueCandidates::new(|| {
+                            current_dependency_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("drop-commits")
+                        .help("also remove the dependency's commits from this branch")
+                        .long("drop-commits")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("delete")
+                .about("delete branches, making their dependents depend on their dependencies")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch (or PR, as `#123` or URL) to delete")
                         .required(true)
-                        .help("branch to remove from the dependencies of this branch")
-                        .num_args(1..),
+                        .num_args(1..)
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("force")
+                        .help("do not ask before deleting branches with unmerged commits")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("show the changes of a branch against its base")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch to diff (defaults to the current branch)")
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("stat")
+                        .help("show a diffstat instead of the patch, as `git diff --stat`")
+                        .long("stat")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("name-only")
+                        .help("show only the names of changed files, as `git diff --name-only`")
+                        .long("name-only")
+                        .conflicts_with("stat")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("check giddy's state for inconsistencies")
+                .arg(
+                    Arg::new("fix")
+                        .help("fix the problems that were found, where possible")
+                        .long("fix")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("print the states of all tracked branches (dependencies, bases, PRs) as JSON, for `giddy import`"),
+        )
+        .subcommand(
+            Command::new("gc").about(
+                "remove the state of deleted branches, making their dependents depend on their dependencies",
+            ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("restore branch states saved by `giddy export`, or take over the branch layout of other stacking tools")
+                .args_conflicts_with_subcommands(true)
+                .subcommand_negates_reqs(true)
+                .arg(
+                    Arg::new("file")
+                        .required(true)
+                        .help("file written by `giddy export`"),
+                )
+                .subcommand(
+                    Command::new("machete")
+                        .about("import the branch layout of git-machete")
+                        .arg(
+                            Arg::new("file")
+                                .help("layout file to read (default: `.git/machete`)"),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("init")
+                .about("set giddy up in this repository: record the default branch and track existing branches")
+                .arg(
+                    Arg::new("default-branch")
+                        .help("use BRANCH as default branch instead of detecting it")
+                        .long("default-branch")
+                        .value_name("BRANCH")
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("adopt")
+                        .help("track all existing branches without asking")
+                        .long("adopt")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("land")
+                .about("merge the PR of a branch that is based on the default branch, once approved and checks passed")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch (or PR, as `#123` or URL) to land (defaults to the current branch)")
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("all")
+                        .help("land the whole current stack bottom-up, restacking and pushing the remaining branches after each merge")
+                        .short('a')
+                        .long("all")
+                        .conflicts_with("branch")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("log")
+                .about("show the commits of each branch in the stack, grouped by branch")
+                .arg(
+                    Arg::new("branch")
+                        .help("show the stack of BRANCH (defaults to the current branch)")
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("stat")
+                        .help("also show which files each commit changed")
+                        .long("stat")
+                        .action(ArgAction::SetTrue),
                 ),
         )
         .subcommand(
             Command::new("new")
-                .about("add a new branch based on the current branch")
-                .arg(Arg::new("name").help("name of the new branch").num_args(1)),
+                .about("add a new branch based on the current branch (or `--from` another one)")
+                .arg(Arg::new("name").help("name of the new branch").num_args(1))
+                .arg(
+                    Arg::new("below")
+                        .help("insert the new branch between the current branch and its dependencies")
+                        .long("below")
+                        .conflicts_with("planned")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("message")
+                        .help("commit the local changes (the staged ones, if any) to the new branch, with message MSG")
+                        .short('m')
+                        .long("message")
+                        .value_name("MSG")
+                        .conflicts_with_all(["below", "insert", "planned"]),
+                )
+                .arg(
+                    Arg::new("from")
+                        .help("base the new branch on BRANCH (or PR) instead of the current branch")
+                        .long("from")
+                        .value_name("BRANCH")
+                        .conflicts_with_all(["below", "insert"])
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("insert")
+                        .help("insert the new branch between the current branch and its dependents")
+                        .long("insert")
+                        .conflicts_with_all(["below", "planned"])
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("planned")
+                        .help("create a placeholder branch (without switching to it), which updates skip until it has commits")
+                        .long("planned")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("next").about(
+                "switch to the branch that depends on the current one \
+                 (for what to do next with the stack, see `giddy suggest`)",
+            ),
+        )
+        .subcommand(
+            Command::new("notes")
+                .about("print a markdown changelog of this branch")
+                .arg(
+                    Arg::new("stack")
+                        .help("include all branches of the current stack, in dependency order")
+                        .short('s')
+                        .long("stack")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("path")
+                .about("show the dependency chain between two branches")
+                .arg(Arg::new("from").required(true).help("branch to start at"))
+                .arg(Arg::new("to").help("branch to end at (defaults to the current branch)")),
+        )
+        .subcommand(
+            Command::new("pr")
+                .about("work with the pull requests of tracked branches")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("push the current branch and open a PR against its base")
+                        .arg(
+                            Arg::new("draft")
+                                .help("open the PR as draft")
+                                .short('d')
+                                .long("draft")
+                                .action(ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("list open PRs of tracked branches, flagging PRs with a wrong base"),
+                ),
+        )
+        .subcommand(
+            Command::new("prev").about("switch to the branch the current one depends on"),
+        )
+        .subcommand(
+            Command::new("prompt").about(
+                "print the current branch and its position in its stack, for shell prompts \
+                 (`!`: needs update)",
+            ),
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("delete branches that have been merged into their base, making their dependents depend on their dependencies")
+                .arg(
+                    Arg::new("remote")
+                        .help("also delete them on the remote")
+                        .short('r')
+                        .long("remote")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("force")
+                        .help("do not ask before deleting")
+                        .short('f')
+                        .long("force")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("push")
+                .about("force-push (with lease) all branches of the current stack"),
+        )
+        .subcommand(
+            Command::new("rename")
+                .about("rename a branch, updating all branches that depend on it")
+                .arg(
+                    Arg::new("old")
+                        .help("branch to rename")
+                        .required(true)
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(Arg::new("new").help("new name").required(true)),
+        )
+        .subcommand(
+            Command::new("reorder")
+                .about("rearrange the branches of the current linear stack")
+                .arg(
+                    Arg::new("branch")
+                        .help("all branches of the stack, in their new order from the bottom up (default: rearrange interactively)")
+                        .num_args(1..)
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                ),
+        )
+        .subcommand(
+            Command::new("roots")
+                .about("list all stack roots, with descendant count and staleness")
+                .arg(
+                    Arg::new("porcelain")
+                        .help("print stable, tab-separated records for scripts")
+                        .long_help(
+                            "print one tab-separated record per stack root, in a format that stays \
+                             stable across releases. Fields: root branch, number of descendants, \
+                             number of branches needing an update. Messages go to stderr.",
+                        )
+                        .long("porcelain")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("share")
+                .about("share branch states with others through a ref on a remote")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("push")
+                        .about("push the states of all tracked branches to the remote")
+                        .arg(remote_arg()),
+                )
+                .subcommand(
+                    Command::new("pull")
+                        .about("fetch shared branch states, for branches that have none yet")
+                        .arg(remote_arg())
+                        .arg(
+                            Arg::new("overwrite")
+                                .help("also replace the states of branches that have one")
+                                .long("overwrite")
+                                .action(ArgAction::SetTrue),
+                        ),
+                ),
         )
         .subcommand(
             Command::new("show")
                 .about("show git branch dependency status")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch to show (defaults to the current branch)")
+                        .conflicts_with_all(["all", "mermaid", "porcelain", "target-branch"])
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("all")
+                        .help("show a one-line status of every tracked branch")
+                        .short('a')
+                        .long("all")
+                        .conflicts_with("tree")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("mermaid")
+                        .help("print the dependency graph as Mermaid diagram")
+                        .long("mermaid")
+                        .conflicts_with_all(["all", "tree"])
+                        .action(ArgAction::SetTrue),
+                )
                 .arg(
                     Arg::new("tree")
                         .help("show dependencies in tree form")
                         .short('t')
                         .long("tree")
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("remote")
+                        .help("also show PR state, reviews and CI checks, as reported by the forge")
+                        .short('r')
+                        .long("remote")
+                        .conflicts_with("mermaid")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    porcelain_arg()
+                        .conflicts_with_all(["mermaid", "tree"]),
                 ),
         )
+        .subcommand(
+            Command::new("split")
+                .about("split the current branch into a stack of branches, without rewriting any commits")
+                .arg(
+                    Arg::new("at")
+                        .help("commit that ends a new branch below the current one (default: pick interactively)")
+                        .long("at")
+                        .value_name("COMMIT")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("name")
+                        .help("names of the new branches, from the bottom up (default: `<branch>-1`, `<branch>-2`, ...)")
+                        .long("name")
+                        .action(ArgAction::Append),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("show which branches of the current stack need an update, are merged or dirty")
+                .arg(
+                    Arg::new("remote")
+                        .help("also show PR state, reviews and CI checks, as reported by the forge")
+                        .short('r')
+                        .long("remote")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(porcelain_arg()),
+        )
+        .subcommand(
+            Command::new("submit")
+                .about("push all branches of the current stack, and create or retarget their PRs (or gerrit changes), listing the stack in each PR description")
+                .arg(
+                    Arg::new("draft")
+                        .help("open new PRs as draft")
+                        .short('d')
+                        .long("draft")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("suggest").about("suggest what to do next with the current stack"),
+        )
+        .subcommand(
+            Command::new("sync")
+                .about("fetch, remove merged branches from the graph, and restack everything")
+                .arg(
+                    Arg::new("pull")
+                        .help("fast-forward the default branch to its upstream first")
+                        .long("pull")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("autostash")
+                        .help("stash local changes before restacking, and restore them afterwards (default: config `autostash`)")
+                        .long("autostash")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("tui")
+                .about("browse, update and reparent branches in a full-screen interface"),
+        )
+        .subcommand(
+            Command::new("track")
+                .visible_alias("adopt")
+                .about("start tracking existing branches, inferring their base from the history")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch(es) to track (defaults to the current branch)")
+                        .num_args(1..)
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("base")
+                        .help("use BRANCH as base instead of inferring it")
+                        .long("base")
+                        .value_name("BRANCH")
+                        .num_args(1)
+                        .add(ArgValueCandidates::new(|| {
+                            branch_candidates().unwrap_or_default()
+                        })),
+                ),
+        )
+        .subcommand(
+            Command::new("undo")
+                .about("undo the last giddy command that changed branches or their state"),
+        )
         .subcommand(
             Command::new("update")
                 .about("rebase git branch on it's dependencies")
+                .arg(
+                    Arg::new("branch")
+                        .help("branch(es) to update (defaults to the current branch)")
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new("all")
+                        .help("update every tracked branch that needs it, in dependency order")
+                        .short('a')
+                        .long("all")
+                        .conflicts_with_all(["branch", "recursive", "onto"])
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .help("with `--all`, restack up to N independent stacks at once, each in a temporary worktree")
+                        .short('j')
+                        .long("jobs")
+                        .value_name("N")
+                        .value_parser(clap::value_parser!(usize))
+                        .requires("all")
+                        .conflicts_with("range-diff"),
+                )
                 .arg(
                     Arg::new("recursive")
                         .help("also update dependencies")
                         .short('r')
                         .long("recursive")
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("with-dependents")
+                        .help("also update all branches that (transitively) depend on the updated ones")
+                        .short('d')
+                        .long("with-dependents")
+                        .conflicts_with("all")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("onto")
+                        .help("make BRANCH the only dependency of this branch, rebase, then update its dependents")
+                        .long("onto")
+                        .value_name("BRANCH")
+                        .num_args(1)
+                        .conflicts_with_all(["recursive", "with-dependents"])
+                        .add(ArgValueCandidates::new(|| {
+                            dependency_candidates(false).unwrap_or_default()
+                        })),
+                )
+                .arg(
+                    Arg::new("push")
+                        .help("force-push (with lease) branches that got rewritten")
+                        .long("push")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("range-diff")
+                        .help("after pushing, post a range-diff to the branch's open PR (default: config `post-range-diff`)")
+                        .long("range-diff")
+                        .requires("push")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("autostash")
+                        .help("stash local changes before updating, and restore them afterwards (default: config `autostash`)")
+                        .long("autostash")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("verify-signatures")
+                        .help("refuse to rebase onto dependencies whose head commit isn't validly signed (default: config `verify-signatures`)")
+                        .long("verify-signatures")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("check")
+                        .help("only check whether the branches need an update (exit code 2 if they do)")
+                        .long("check")
+                        .conflicts_with_all(["onto", "push", "autostash", "jobs"])
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("continue")
+                        .help("continue an update that stopped on a conflict, once it is resolved")
+                        .long("continue")
+                        .conflicts_with_all(["branch", "all", "recursive", "with-dependents", "onto", "push", "autostash", "abort", "check"])
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("abort")
+                        .help("abort an update that stopped on a conflict, restoring all branches it touched")
+                        .long("abort")
+                        .conflicts_with_all(["branch", "all", "recursive", "with-dependents", "onto", "push", "autostash", "check"])
+                        .action(ArgAction::SetTrue),
                 ),
         )
 }
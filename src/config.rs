@@ -0,0 +1,254 @@
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::output::warning;
+
+/// When to use colors in output.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => std::io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// Where branches get reviewed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    /// pull requests on GitHub, through `gh`
+    #[default]
+    Github,
+    /// pull requests on Gitea or Forgejo, through their API
+    Gitea,
+    /// changes pushed to `refs/for/<default branch>`
+    Gerrit,
+}
+
+/// How giddy runs the git queries in its hot paths, see [`crate::backend`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// spawn `git` for each query
+    #[default]
+    Cli,
+    /// answer queries in-process with gitoxide
+    Gix,
+}
+
+/// A `branch-remotes` entry.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BranchRemote {
+    /// branch name pattern, `*` matches anything
+    pub branches: String,
+    pub remote: String,
+}
+
+/// Settings from giddy's config files.
+///
+/// Read from `~/.config/giddy/config.toml`, then from `.git/giddy/.config.toml`
+/// in the repository, whose settings take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// use this as default branch instead of detecting it
+    pub default_branch: Option<String>,
+    /// branches that giddy refuses to rebase, delete or reparent, besides
+    /// the default branch; `*` matches anything, e.g., `release/*`
+    pub protected_branches: Vec<String>,
+    /// remote to push to, unless git has one configured for the branch
+    pub push_remote: Option<String>,
+    /// remotes to push branches to by name, first match wins, e.g.,
+    /// `[{ branches = "me/*", remote = "fork" }]`
+    pub branch_remotes: Vec<BranchRemote>,
+    /// push branches that `update` rewrote, as if `--push` was given
+    pub auto_push: bool,
+    /// stash local changes during `update`, as if `--autostash` was given
+    pub autostash: bool,
+    pub color: ColorChoice,
+    /// where `submit` sends branches for review; detected from the remote URL
+    /// if unset
+    pub forge: Option<ForgeKind>,
+    /// web URL of the repository on the forge, if the remote URL doesn't
+    /// tell, e.g., `https://git.example.com/owner/repo`
+    pub forge_url: Option<String>,
+    /// names for `giddy new` without one, e.g., `{user}/{parent}/{seq}`, see
+    /// [`crate::naming::new_branch_name()`]
+    pub branch_name_template: Option<String>,
+    /// extra arguments for `git rebase`, e.g., `--committer-date-is-author-date`
+    pub rebase_flags: Vec<String>,
+    /// after `update` pushed a branch, post a range-diff to its open PR, as
+    /// if `--range-diff` was given
+    pub post_range_diff: bool,
+    /// refuse to rebase onto unsigned dependencies, as if
+    /// `--verify-signatures` was given
+    pub verify_signatures: bool,
+    /// write a commit-graph file if there is none, speeding up ancestry queries
+    pub write_commit_graph: bool,
+    pub backend: BackendKind,
+}
+
+impl Config {
+    /// Loads the user config and the config of the repository at `git_dir`.
+    pub fn load(git_dir: &Utf8Path) -> Result<Self> {
+        let mut table = toml::Table::new();
+        for path in [Self::user_path(), Some(Self::repo_path(git_dir))]
+            .into_iter()
+            .flatten()
+        {
+            if !path.exists() {
+                continue;
+            }
+            let contents =
+                std::fs::read_to_string(&path).with_context(|| format!("reading `{path}`"))?;
+            let layer: toml::Table =
+                toml::from_str(&contents).with_context(|| format!("parsing `{path}`"))?;
+            table.extend(layer);
+        }
+
+        table.try_into().context("invalid giddy config")
+    }
+
+    /// Like [`Config::load()`], but falls back to the defaults (warning once per run).
+    pub fn load_or_default(git_dir: &Utf8Path) -> Self {
+        static WARNED: AtomicBool = AtomicBool::new(false);
+
+        Self::load(git_dir).unwrap_or_else(|e| {
+            if !WARNED.swap(true, Ordering::Relaxed) {
+                warning!("{}, using default settings", format!("{e:#}").trim_end());
+            }
+            Self::default()
+        })
+    }
+
+    pub fn repo_path(git_dir: &Utf8Path) -> Utf8PathBuf {
+        git_dir.join("giddy").join(".config.toml")
+    }
+
+    fn user_path() -> Option<Utf8PathBuf> {
+        let config_home = std::env::var("XDG_CONFIG_HOME")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .map(Utf8PathBuf::from)
+            .or_else(|| {
+                std::env::var("HOME")
+                    .ok()
+                    .map(|home| Utf8PathBuf::from(home).join(".config"))
+            })?;
+
+        Some(config_home.join("giddy").join("config.toml"))
+    }
+
+    pub fn is_protected(&self, branch: &str) -> bool {
+        self.protected_branches
+            .iter()
+            .any(|pattern| glob_match(pattern, branch))
+    }
+
+    /// Returns the remote `branch-remotes` maps `branch` to.
+    pub fn remote_for(&self, branch: &str) -> Option<&str> {
+        self.branch_remotes
+            .iter()
+            .find(|entry| glob_match(&entry.branches, branch))
+            .map(|entry| entry.remote.as_str())
+    }
+}
+
+/// Returns whether `name` matches `pattern`, in which `*` stands for any
+/// (possibly empty) text.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = parts.next_back() else {
+        // no `*` at all
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_without_star() {
+        assert!(glob_match("main", "main"));
+        assert!(!glob_match("main", "main2"));
+        assert!(!glob_match("main", "mai"));
+        assert!(!glob_match("", "main"));
+    }
+
+    #[test]
+    fn glob_match_star_as_prefix() {
+        assert!(glob_match("*-wip", "feature-wip"));
+        assert!(glob_match("*-wip", "-wip"));
+        assert!(!glob_match("*-wip", "feature-wip2"));
+    }
+
+    #[test]
+    fn glob_match_star_as_suffix() {
+        assert!(glob_match("release/*", "release/1.2"));
+        assert!(glob_match("release/*", "release/"));
+        assert!(!glob_match("release/*", "releases/1.2"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn glob_match_star_as_infix() {
+        assert!(glob_match("me/*/wip", "me/parser/wip"));
+        assert!(glob_match("me/*/wip", "me//wip"));
+        assert!(!glob_match("me/*/wip", "me/parser/done"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        // the parts must not overlap
+        assert!(!glob_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn remote_for_picks_the_first_match() {
+        let config = Config {
+            branch_remotes: vec![
+                BranchRemote {
+                    branches: "me/*".into(),
+                    remote: "fork".into(),
+                },
+                BranchRemote {
+                    branches: "*".into(),
+                    remote: "origin".into(),
+                },
+            ],
+            ..Default::default()
+        };
+        assert_eq!(config.remote_for("me/parser"), Some("fork"));
+        assert_eq!(config.remote_for("parser"), Some("origin"));
+        assert_eq!(Config::default().remote_for("parser"), None);
+    }
+}
@@ -0,0 +1,82 @@
+//! Failures that callers may want to tell apart.
+//!
+//! Functions still return [`anyhow::Result`], but for these failures the
+//! error (or one of its contexts) is an [`Error`], so it can be matched on:
+//!
+//! ```no_run
+//! # let repo = giddy::git::Repo::open().unwrap();
+//! match repo.branch_current() {
+//!     Err(e) if matches!(e.downcast_ref(), Some(giddy::Error::DetachedHead)) => {}
+//!     _ => {}
+//! }
+//! ```
+
+use camino::Utf8PathBuf;
+
+/// Some kinds also get their own exit code, see [`Error::exit_code()`].
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("not a git repository")]
+    NotARepo,
+    #[error("not on a branch (HEAD is detached)")]
+    DetachedHead,
+    #[error("branch `{0}` not found")]
+    BranchNotFound(String),
+    #[error("branch `{0}` is not tracked")]
+    NotTracked(String),
+    /// from `giddy update --check`
+    #[error("{} need(s) an update", .0.iter().map(|name| format!("`{name}`")).collect::<Vec<_>>().join(", "))]
+    NeedsUpdate(Vec<String>),
+    /// a rebase stopped on conflicts, which are left to be resolved
+    #[error("rebasing `{branch}` onto `{onto}` stopped on conflicts")]
+    RebaseConflict { branch: String, onto: String },
+    /// an update stopped on conflicts, see `giddy update --continue`
+    #[error("update stopped at branch `{0}`: resolve the conflicts, then run `giddy update --continue` (or `giddy update --abort`)")]
+    UpdateStopped(String),
+    #[error("adding `{dep}` as dependency of `{branch}` would create a cycle")]
+    CycleDetected { branch: String, dep: String },
+    #[error("cannot determine fork point between `{branch}` and `{base}`, has it been merged?")]
+    ForkPointUnknown { branch: String, base: String },
+    /// the default branch and `protected-branches` are never rebased,
+    /// deleted or reparented
+    #[error("branch `{branch}` is protected, refusing to {action} it")]
+    Protected { branch: String, action: String },
+    /// git refuses to `action` a branch that is checked out elsewhere
+    #[error("cannot {action} branch `{branch}`, it is checked out in worktree `{worktree}`")]
+    CheckedOutElsewhere {
+        branch: String,
+        action: String,
+        worktree: Utf8PathBuf,
+    },
+    /// the state was written by a newer giddy
+    #[error("state has version {0}, but this giddy only knows up to version {max}, please upgrade giddy", max = crate::git::STATE_VERSION)]
+    StateTooNew(u32),
+    /// answering a prompt is needed, but prompts are disabled
+    #[error("prompt-required: {0}")]
+    PromptRequired(String),
+    /// from `giddy check`, with the names of the failed checks
+    #[error("check failed: {}", .0.join(", "))]
+    CheckFailed(Vec<String>),
+}
+
+impl Error {
+    /// Returns the exit code of `giddy` for this error:
+    ///
+    /// - 2: branches need an update ([`Error::NeedsUpdate`])
+    /// - 3: stopped on conflicts ([`Error::RebaseConflict`], [`Error::UpdateStopped`])
+    /// - 4: branch not tracked ([`Error::NotTracked`])
+    /// - 5: dependency cycle ([`Error::CycleDetected`])
+    /// - 6: `giddy check` found problems ([`Error::CheckFailed`])
+    /// - 1: anything else
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NeedsUpdate(_) => 2,
+            Error::RebaseConflict { .. } | Error::UpdateStopped(_) => 3,
+            Error::NotTracked(_) => 4,
+            Error::CycleDetected { .. } => 5,
+            Error::CheckFailed(_) => 6,
+            _ => 1,
+        }
+    }
+}
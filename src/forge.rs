@@ -0,0 +1,593 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Context, Result};
+use itertools::Itertools;
+use serde::Deserialize;
+
+use crate::{config::ForgeKind, gerrit::Gerrit, git::skip_in_dry_run, gitea::Gitea};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequest {
+    pub number: u32,
+    pub head_ref_name: String,
+    pub base_ref_name: String,
+    pub state: String,
+    pub url: String,
+}
+
+/// Review and CI state of a PR.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrStatus {
+    /// `OPEN`, `CLOSED` or `MERGED`
+    pub state: String,
+    /// `APPROVED`, `CHANGES_REQUESTED` or `REVIEW_REQUIRED`, if reviews are required
+    #[serde(default)]
+    pub review_decision: Option<String>,
+    #[serde(default)]
+    pub status_check_rollup: Vec<Check>,
+}
+
+/// A check run or commit status of a PR.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Check {
+    /// check runs: `QUEUED`, `IN_PROGRESS` or `COMPLETED`
+    #[serde(default)]
+    pub status: Option<String>,
+    /// check runs: `SUCCESS`, `FAILURE`, `SKIPPED`, ...
+    #[serde(default)]
+    pub conclusion: Option<String>,
+    /// commit statuses: `SUCCESS`, `FAILURE`, `ERROR` or `PENDING`
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+impl Check {
+    fn is_pending(&self) -> bool {
+        match &self.state {
+            Some(state) => state == "PENDING" || state == "EXPECTED",
+            None => self.status.as_deref() != Some("COMPLETED"),
+        }
+    }
+
+    fn is_failure(&self) -> bool {
+        let result = self.state.as_deref().or(self.conclusion.as_deref());
+        matches!(
+            result,
+            Some("FAILURE" | "ERROR" | "TIMED_OUT" | "CANCELLED" | "ACTION_REQUIRED")
+        )
+    }
+}
+
+impl PrStatus {
+    /// Returns whether reviews and checks allow merging: approved (or no
+    /// review required), and no check failed or is still running.
+    pub fn is_ready(&self) -> bool {
+        let approved = match self.review_decision.as_deref() {
+            None | Some("") | Some("APPROVED") => true,
+            Some(_) => false,
+        };
+        approved
+            && self
+                .status_check_rollup
+                .iter()
+                .all(|check| !check.is_failure() && !check.is_pending())
+    }
+
+    /// Returns a short description, like `open, approved, checks passed`.
+    pub fn summary(&self) -> String {
+        let mut parts = vec![self.state.to_lowercase()];
+        if let Some(review) = self.review_decision.as_deref().filter(|r| !r.is_empty()) {
+            parts.push(review.to_lowercase().replace('_', " "));
+        }
+
+        let checks = &self.status_check_rollup;
+        if !checks.is_empty() {
+            let failed = checks.iter().filter(|check| check.is_failure()).count();
+            let pending = checks.iter().filter(|check| check.is_pending()).count();
+            parts.push(if failed > 0 {
+                format!("{failed} of {} checks failed", checks.len())
+            } else if pending > 0 {
+                format!("{pending} of {} checks pending", checks.len())
+            } else {
+                "checks passed".to_string()
+            });
+        }
+
+        parts.join(", ")
+    }
+}
+
+/// Parses a PR reference (`#123` or a PR URL like
+/// `https://github.com/owner/repo/pull/123`) into the PR number.
+///
+/// Plain numbers are not PR references, as they are valid branch names.
+pub fn parse_pr_ref(pr_ref: &str) -> Option<u32> {
+    if let Some(number) = pr_ref.strip_prefix('#') {
+        return number.parse().ok();
+    }
+
+    if pr_ref.starts_with("https://") || pr_ref.starts_with("http://") {
+        let mut segments = pr_ref.trim_end_matches('/').rsplit('/');
+        let number = segments.next()?;
+        let kind = segments.next()?;
+        if kind == "pull" || kind == "pulls" || kind == "merge_requests" {
+            return number.parse().ok();
+        }
+    }
+
+    None
+}
+
+/// The pull request operations of a code forge.
+///
+/// Forges that lack an operation keep the default, which fails.
+pub trait Forge: std::fmt::Debug {
+    /// Returns the forge's name, for messages.
+    fn name(&self) -> &'static str;
+
+    fn unsupported(&self, what: &str) -> anyhow::Error {
+        anyhow!("{what} is not supported with {}", self.name())
+    }
+
+    /// Returns the name of the head branch of PR `number`.
+    fn pr_head_branch(&self, number: u32) -> Result<String> {
+        Ok(self.pr_view(number)?.head_ref_name)
+    }
+
+    /// Returns the state of PR `number` (`OPEN`, `CLOSED` or `MERGED`).
+    fn pr_state(&self, number: u32) -> Result<String> {
+        Ok(self.pr_view(number)?.state)
+    }
+
+    /// Adds a comment to PR `number`.
+    fn pr_comment(&self, number: u32, body: &str) -> Result<()> {
+        let _ = (number, body);
+        Err(self.unsupported("commenting on PRs"))
+    }
+
+    /// Returns all open PRs.
+    fn list_prs(&self) -> Result<Vec<PullRequest>> {
+        Err(self.unsupported("listing PRs"))
+    }
+
+    /// Creates a PR for `branch` against `base`, returning its number and URL.
+    ///
+    /// `head` is `branch`, or `owner:branch` for a branch in a fork, see
+    /// [`crate::git::Repo::pr_head()`]. Title and description are taken
+    /// from the branch's commits.
+    fn create_pr(
+        &self,
+        branch: &str,
+        head: &str,
+        base: &str,
+        draft: bool,
+    ) -> Result<(u32, String)> {
+        let _ = (branch, head, base, draft);
+        Err(self.unsupported("creating PRs"))
+    }
+
+    /// Returns details of PR `number`.
+    fn pr_view(&self, number: u32) -> Result<PullRequest> {
+        let _ = number;
+        Err(self.unsupported("looking up PRs"))
+    }
+
+    /// Returns review and CI state of PR `number`.
+    fn pr_status(&self, number: u32) -> Result<PrStatus> {
+        let _ = number;
+        Err(self.unsupported("PR status"))
+    }
+
+    /// Returns the description of PR `number`.
+    fn pr_body(&self, number: u32) -> Result<String> {
+        let _ = number;
+        Err(self.unsupported("PR descriptions"))
+    }
+
+    /// Replaces the description of PR `number` with `body`.
+    fn set_pr_body(&self, number: u32, body: &str) -> Result<()> {
+        let _ = (number, body);
+        Err(self.unsupported("editing PR descriptions"))
+    }
+
+    /// Opens PR `number` in the browser.
+    fn open_pr(&self, number: u32) -> Result<()> {
+        open_url(&self.pr_view(number)?.url)
+    }
+
+    /// Changes the base branch of PR `number` to `base`.
+    fn set_pr_base(&self, number: u32, base: &str) -> Result<()> {
+        let _ = (number, base);
+        Err(self.unsupported("retargeting PRs"))
+    }
+
+    /// Merges PR `number`.
+    fn merge_pr(&self, number: u32) -> Result<()> {
+        let _ = number;
+        Err(self.unsupported("merging PRs"))
+    }
+}
+
+/// Opens `url` in the browser.
+pub fn open_url(url: &str) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    let status = Command::new(opener)
+        .arg(url)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to execute `{opener}`"))?;
+
+    status
+        .success()
+        .then_some(())
+        .ok_or_else(|| anyhow!("opening `{url}` failed"))
+}
+
+/// Splits a remote or web URL of a repository into web base URL, owner and
+/// repository name.
+///
+/// Handles `https://host/owner/repo`, `ssh://git@host:22/owner/repo.git` and
+/// `git@host:owner/repo.git`. SSH remotes are assumed to be served over HTTPS
+/// on the same host.
+pub fn parse_repo_url(url: &str) -> Option<(String, String, String)> {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+
+    let (base, path) = if let Some((scheme, rest)) = url.split_once("://") {
+        let (host, path) = rest.split_once('/')?;
+        let host = host.rsplit('@').next()?;
+        match scheme {
+            "http" | "https" => (format!("{scheme}://{host}"), path),
+            // the SSH port is no use for the API
+            _ => (format!("https://{}", host.split(':').next()?), path),
+        }
+    } else {
+        let (host, path) = url.split_once(':')?;
+        (format!("https://{}", host.rsplit('@').next()?), path)
+    };
+
+    let (name, owner) = path.rsplit('/').take(2).collect_tuple::<(_, _)>()?;
+    Some((base, owner.to_string(), name.to_string()))
+}
+
+/// Which forge hosts the repository.
+///
+/// Without `forge` in the config, GitHub is assumed unless the URL of
+/// `remote_url` looks like a Gitea or Forgejo instance.
+pub fn detect(configured: Option<ForgeKind>, remote_url: &str) -> ForgeKind {
+    if let Some(kind) = configured {
+        return kind;
+    }
+
+    let url = remote_url.to_lowercase();
+    if ["gitea", "forgejo", "codeberg.org"]
+        .iter()
+        .any(|hint| url.contains(hint))
+    {
+        ForgeKind::Gitea
+    } else {
+        ForgeKind::Github
+    }
+}
+
+/// Returns the forge implementation for `kind`, for the repository at
+/// `remote_url`.
+pub fn from_kind(kind: ForgeKind, remote_url: &str) -> Result<Box<dyn Forge>> {
+    Ok(match kind {
+        ForgeKind::Github => Box::new(GitHub),
+        ForgeKind::Gitea => Box::new(Gitea::new(remote_url)?),
+        ForgeKind::Gerrit => Box::new(Gerrit),
+    })
+}
+
+/// GitHub, through the `gh` CLI.
+#[derive(Debug)]
+pub struct GitHub;
+
+impl GitHub {
+    fn gh(&self) -> Command {
+        Command::new("gh")
+    }
+}
+
+impl Forge for GitHub {
+    fn name(&self) -> &'static str {
+        "GitHub"
+    }
+
+    fn pr_head_branch(&self, number: u32) -> Result<String> {
+        let output = self
+            .gh()
+            .args(["pr", "view", &number.to_string()])
+            .args(["--json", "headRefName", "--jq", ".headRefName"])
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "looking up PR #{number} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
+    }
+
+    fn pr_comment(&self, number: u32, body: &str) -> Result<()> {
+        let args = ["pr", "comment", &number.to_string(), "--body-file", "-"];
+        if skip_in_dry_run("gh", args) {
+            return Ok(());
+        }
+
+        let mut child = self
+            .gh()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("failed to execute `gh`")?;
+        child.stdin.take().unwrap().write_all(body.as_bytes())?;
+
+        if !child.wait()?.success() {
+            return Err(anyhow!("commenting on PR #{number} failed"));
+        }
+
+        Ok(())
+    }
+
+    fn list_prs(&self) -> Result<Vec<PullRequest>> {
+        let output = self
+            .gh()
+            .args(["pr", "list", "--state", "open", "--limit", "1000"])
+            .args(["--json", "number,headRefName,baseRefName,state,url"])
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "listing PRs failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn create_pr(
+        &self,
+        branch: &str,
+        head: &str,
+        base: &str,
+        draft: bool,
+    ) -> Result<(u32, String)> {
+        let mut args = vec!["pr", "create", "--fill", "--head", head, "--base", base];
+        if draft {
+            args.push("--draft");
+        }
+        if skip_in_dry_run("gh", &args) {
+            return Ok((0, format!("(new PR for `{branch}`)")));
+        }
+        let output = self
+            .gh()
+            .args(args)
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "creating PR for `{branch}` failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let stdout = String::from_utf8(output.stdout)?;
+        stdout
+            .lines()
+            .map(str::trim)
+            .rev()
+            .find_map(|line| Some((parse_pr_ref(line)?, line.to_string())))
+            .ok_or_else(|| anyhow!("cannot find PR URL in `gh` output"))
+    }
+
+    fn pr_view(&self, number: u32) -> Result<PullRequest> {
+        let output = self
+            .gh()
+            .args(["pr", "view", &number.to_string()])
+            .args(["--json", "number,headRefName,baseRefName,state,url"])
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "looking up PR #{number} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn pr_status(&self, number: u32) -> Result<PrStatus> {
+        let output = self
+            .gh()
+            .args(["pr", "view", &number.to_string()])
+            .args(["--json", "state,reviewDecision,statusCheckRollup"])
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "looking up PR #{number} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+
+    fn pr_body(&self, number: u32) -> Result<String> {
+        let output = self
+            .gh()
+            .args(["pr", "view", &number.to_string()])
+            .args(["--json", "body", "--jq", ".body"])
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "looking up PR #{number} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let body = String::from_utf8(output.stdout)?;
+        // `--jq` adds a newline
+        Ok(body.strip_suffix('\n').unwrap_or(&body).to_string())
+    }
+
+    fn set_pr_body(&self, number: u32, body: &str) -> Result<()> {
+        let args = ["pr", "edit", &number.to_string(), "--body-file", "-"];
+        if skip_in_dry_run("gh", args) {
+            return Ok(());
+        }
+
+        let mut child = self
+            .gh()
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("failed to execute `gh`")?;
+        child.stdin.take().unwrap().write_all(body.as_bytes())?;
+
+        if !child.wait()?.success() {
+            return Err(anyhow!("editing description of PR #{number} failed"));
+        }
+
+        Ok(())
+    }
+
+    fn open_pr(&self, number: u32) -> Result<()> {
+        let output = self
+            .gh()
+            .args(["pr", "view", &number.to_string(), "--web"])
+            .output()
+            .context("failed to execute `gh`")?;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "opening PR #{number} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn set_pr_base(&self, number: u32, base: &str) -> Result<()> {
+        let args = ["pr", "edit", &number.to_string(), "--base", base];
+        if skip_in_dry_run("gh", args) {
+            return Ok(());
+        }
+
+        let status = self
+            .gh()
+            .args(args)
+            .stdout(Stdio::null())
+            .status()
+            .context("failed to execute `gh`")?;
+
+        if !status.success() {
+            return Err(anyhow!("changing base of PR #{number} to `{base}` failed"));
+        }
+
+        Ok(())
+    }
+
+    fn merge_pr(&self, number: u32) -> Result<()> {
+        let args = ["pr", "merge", &number.to_string(), "--merge"];
+        if skip_in_dry_run("gh", args) {
+            return Ok(());
+        }
+
+        let output = self
+            .gh()
+            .args(args)
+            .output()
+            .context("failed to execute `gh`")?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "merging PR #{number} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(base: &str, owner: &str, name: &str) -> Option<(String, String, String)> {
+        Some((base.to_string(), owner.to_string(), name.to_string()))
+    }
+
+    #[test]
+    fn parse_https_url() {
+        assert_eq!(
+            parse_repo_url("https://github.com/owner/repo"),
+            parsed("https://github.com", "owner", "repo")
+        );
+        assert_eq!(
+            parse_repo_url("https://github.com/owner/repo.git/"),
+            parsed("https://github.com", "owner", "repo")
+        );
+        assert_eq!(
+            parse_repo_url("http://user@git.example.com/owner/repo.git"),
+            parsed("http://git.example.com", "owner", "repo")
+        );
+    }
+
+    #[test]
+    fn parse_ssh_url() {
+        assert_eq!(
+            parse_repo_url("ssh://git@git.example.com:2222/owner/repo.git"),
+            parsed("https://git.example.com", "owner", "repo")
+        );
+        assert_eq!(
+            parse_repo_url("ssh://github.com/owner/repo"),
+            parsed("https://github.com", "owner", "repo")
+        );
+    }
+
+    #[test]
+    fn parse_scp_url() {
+        assert_eq!(
+            parse_repo_url("git@github.com:owner/repo.git"),
+            parsed("https://github.com", "owner", "repo")
+        );
+        assert_eq!(
+            parse_repo_url("codeberg.org:owner/repo"),
+            parsed("https://codeberg.org", "owner", "repo")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_url() {
+        assert_eq!(parse_repo_url("repo"), None);
+        assert_eq!(parse_repo_url("https://github.com/repo"), None);
+    }
+}
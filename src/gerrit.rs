@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use itertools::Itertools;
+
+use crate::{
+    forge::Forge,
+    git::{skip_in_dry_run, Branch, Repo},
+    output::info,
+};
+
+/// Gerrit has changes instead of PRs, see `giddy submit`.
+#[derive(Debug)]
+pub struct Gerrit;
+
+impl Forge for Gerrit {
+    fn name(&self) -> &'static str {
+        "gerrit"
+    }
+}
+
+/// Returns whether `commit`'s message has a `Change-Id` trailer.
+fn has_change_id(repo: &Repo, commit: &str) -> Result<bool> {
+    Ok(!repo
+        .cmd_output([
+            "log",
+            "-1",
+            "--format=%(trailers:key=Change-Id,valueonly)",
+            commit,
+        ])?
+        .trim()
+        .is_empty())
+}
+
+/// Creates a copy of `commit` on top of `parent`, adding a `Change-Id`
+/// trailer to its message if `add_change_id`. Returns the new commit id.
+fn rewrite_commit(repo: &Repo, commit: &str, parent: &str, add_change_id: bool) -> Result<String> {
+    let info = repo.cmd_output([
+        "log",
+        "-1",
+        "--date=raw",
+        "--format=%T%x00%an%x00%ae%x00%ad",
+        commit,
+    ])?;
+    let Some((tree, name, email, date)) = info.trim().split('\0').collect_tuple() else {
+        return Err(anyhow!("cannot read commit {commit}"));
+    };
+
+    let mut message = repo.cmd_output(["log", "-1", "--format=%B", commit])?;
+    if add_change_id {
+        // the old commit id is as unique as gerrit's own hook would make it
+        message = repo.cmd_output_with_input(
+            [
+                "interpret-trailers",
+                "--trailer",
+                &format!("Change-Id: I{commit}"),
+            ],
+            &message,
+        )?;
+    }
+
+    let output = repo
+        .git()
+        .args(["commit-tree", tree, "-p", parent, "-F", "-"])
+        .env("GIT_AUTHOR_NAME", name)
+        .env("GIT_AUTHOR_EMAIL", email)
+        .env("GIT_AUTHOR_DATE", date)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            use std::io::Write;
+            child.stdin.take().unwrap().write_all(message.as_bytes())?;
+            child.wait_with_output()
+        })
+        .context("failed to execute git")?;
+    if !output.status.success() {
+        return Err(anyhow!("rewriting commit {commit} failed"));
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
+/// Gives every commit of `branches` (in dependency order) a `Change-Id`
+/// trailer, so gerrit can track them across rebases. Returns the branches
+/// that got rewritten.
+///
+/// Only commit messages change, so this can't conflict. Branches on top of
+/// rewritten ones are moved along.
+pub fn add_change_ids(repo: &Repo, branches: &[String]) -> Result<Vec<String>> {
+    let mut rewritten: HashMap<String, String> = HashMap::new();
+    let mut new_heads = Vec::new();
+    let mut added = 0;
+
+    for name in branches {
+        let branch = Branch::new(name, repo)?;
+        let commits = repo.cmd_output_vec(
+            ["rev-list", "--reverse", "--parents"]
+                .into_iter()
+                .chain(branch.own_commit_revs().iter().map(String::as_str)),
+        )?;
+
+        for line in commits.iter().filter(|line| !line.is_empty()) {
+            let mut ids = line.split(' ');
+            let commit = ids.next().unwrap();
+            let parents = ids.collect_vec();
+            let [parent] = parents[..] else {
+                return Err(anyhow!(
+                    "branch `{name}` contains merge commit {commit}, gerrit needs a linear history"
+                ));
+            };
+
+            let new_parent = rewritten.get(parent).map_or(parent, String::as_str);
+            let needs_change_id = !has_change_id(repo, commit)?;
+            if !needs_change_id && new_parent == parent {
+                continue;
+            }
+            let new_commit = rewrite_commit(repo, commit, new_parent, needs_change_id)?;
+            added += usize::from(needs_change_id);
+            rewritten.insert(commit.to_string(), new_commit);
+        }
+
+        let head = branch.head()?;
+        if let Some(new_head) = rewritten.get(&head) {
+            new_heads.push((name, new_head.clone()));
+        }
+    }
+
+    for (name, new_head) in &new_heads {
+        repo.reset_branch(name, new_head)?;
+        let mut branch = Branch::new(name, repo)?;
+        if let Some(base_commit) = branch.state.base_commit.as_ref() {
+            if let Some(new_base) = rewritten.get(base_commit) {
+                branch.state.base_commit = Some(new_base.clone());
+                branch.save_state()?;
+            }
+        }
+    }
+    if added > 0 {
+        info!("giddy: added Change-Id to {added} commit(s)");
+    }
+
+    Ok(new_heads
+        .into_iter()
+        .map(|(name, _)| name.clone())
+        .collect())
+}
+
+/// Pushes `branch` for review on `target`, with the branch name as topic.
+///
+/// The changes of a stack end up in one relation chain, as each commit's
+/// parent is the change below it.
+pub fn push_for_review(repo: &Repo, branch: &str, target: &str) -> Result<()> {
+    let remote = repo.push_remote(branch);
+    let refspec = format!("{branch}:refs/for/{target}%topic={branch}");
+    let args = ["push", "--quiet", remote.as_str(), refspec.as_str()];
+    if skip_in_dry_run("git", args) {
+        return Ok(());
+    }
+
+    info!("giddy: pushing `{branch}` to `{remote}` for review on `{target}`...");
+    let output = repo.git().args(args).output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // gerrit rejects pushes of changes it has already
+    if output.status.success() || stderr.contains("no new changes") {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "pushing `{branch}` for review failed: {}",
+            stderr.trim()
+        ))
+    }
+}
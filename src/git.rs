@@ -1,9 +1,12 @@
 use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
     ffi::OsStr,
     fs::File,
-    io::{BufReader, BufWriter},
+    io::{BufReader, BufWriter, Write},
     path::Path,
-    process::Command,
+    process::{Child, Command, ExitStatus, Output, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
 use anyhow::{anyhow, Context, Result};
@@ -12,13 +15,191 @@ use indexmap::IndexSet;
 use itertools::Itertools;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::graph::GraphRepo;
+use crate::{
+    backend::{self, GitBackend},
+    cache::Cache,
+    config::Config,
+    error::Error,
+    forge::{self, Forge},
+    graph::GraphRepo,
+    hooks::{self, Hook},
+    output::{self, info, warning},
+    ui,
+};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Makes commands that change something (in git, in giddy's state or on the
+/// forge) print what they would do instead of doing it.
+pub fn set_dry_run() {
+    DRY_RUN.store(true, Ordering::Relaxed);
+}
+
+pub fn is_dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Returns `program` and `args` as a shell-like command line.
+fn command_line<I, S>(program: &str, args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args = args
+        .into_iter()
+        .map(|arg| {
+            let arg = arg.as_ref().to_string_lossy();
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                format!("'{arg}'")
+            } else {
+                arg.into_owned()
+            }
+        })
+        .join(" ");
+
+    format!("{program} {args}")
+}
+
+/// Logs the command line of `program` (at debug level).
+fn trace_command<I, S>(program: &str, args: I)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    tracing::debug!("+ {}", command_line(program, args));
+}
+
+/// Returns the error for a failed git invocation, with git's own message.
+fn git_failed<S: AsRef<OsStr>>(args: &[S], stderr: &[u8]) -> anyhow::Error {
+    let command = command_line("git", args);
+    let stderr = String::from_utf8_lossy(stderr);
+    match stderr.trim() {
+        "" => anyhow!("`{command}` failed"),
+        message => anyhow!("`{command}` failed: {message}"),
+    }
+}
 
+/// Logs the captured `output` of a command (at trace level).
+fn trace_output(output: &[u8]) {
+    if tracing::enabled!(tracing::Level::TRACE) {
+        for line in String::from_utf8_lossy(output).lines() {
+            tracing::trace!("  {line}");
+        }
+    }
+}
+
+/// A `git` invocation that logs its command line (and output, if captured)
+/// when it runs, so `-v` shows every git command giddy runs.
+#[derive(Debug)]
+pub struct GitCommand(Command);
+
+impl GitCommand {
+    pub fn new() -> Self {
+        Self(Command::new("git"))
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Self {
+        self.0.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.0.args(args);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) -> &mut Self {
+        self.0.env(key, value);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.0.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.0.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Self {
+        self.0.stderr(cfg);
+        self
+    }
+
+    pub fn status(&mut self) -> std::io::Result<ExitStatus> {
+        self.trace();
+        self.0.status()
+    }
+
+    pub fn output(&mut self) -> std::io::Result<Output> {
+        self.trace();
+        let output = self.0.output()?;
+        trace_output(&output.stdout);
+        trace_output(&output.stderr);
+        Ok(output)
+    }
+
+    pub fn spawn(&mut self) -> std::io::Result<Child> {
+        self.trace();
+        self.0.spawn()
+    }
+
+    fn trace(&self) {
+        trace_command("git", self.0.get_args());
+    }
+}
+
+impl Default for GitCommand {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// In dry-run mode, prints the command line of `program` and returns `true`,
+/// meaning the caller should not run it.
+pub fn skip_in_dry_run<I, S>(program: &str, args: I) -> bool
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    if !is_dry_run() {
+        return false;
+    }
+
+    println!("giddy: would run: {}", command_line(program, args));
+
+    true
+}
+
+/// The git repository in the current directory, with giddy's state of it.
 #[derive(Debug)]
 pub struct Repo {
     git_dir: Utf8PathBuf,
+    /// git dir of the current worktree, with its in-progress rebase or merge
+    worktree_git_dir: Utf8PathBuf,
+    backend: Box<dyn GitBackend>,
+    config: Config,
+    default_branch: OnceCell<String>,
+    forge: OnceCell<Box<dyn Forge>>,
+    /// remote-tracking branches, e.g., `origin/main`
+    remote_branches: OnceCell<Vec<String>>,
+    verify_signatures: bool,
+    /// branches containing a commit, keyed by commit
+    contains_cache: RefCell<HashMap<String, Vec<String>>>,
+    /// branches merged into a branch, keyed by branch
+    merged_cache: RefCell<HashMap<String, Vec<String>>>,
+    /// heads of all local branches, read at once on first use
+    heads: RefCell<Option<Vec<(String, String)>>>,
+    cache: RefCell<Cache>,
 }
 
+/// A local branch of a [`Repo`], with the state giddy keeps for it.
 #[derive(Debug, Clone)]
 pub struct Branch<'a> {
     name: String,
@@ -26,63 +207,571 @@ pub struct Branch<'a> {
     pub state: BranchState,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+/// File counts from `git status`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WorktreeStatus {
+    pub staged: usize,
+    pub unstaged: usize,
+    pub untracked: usize,
+}
+
+/// Version of the [`BranchState`] format that this giddy writes.
+///
+/// Older states get migrated on load, see [`BranchState::migrate()`].
+pub const STATE_VERSION: u32 = 1;
+
+/// What giddy records about a branch, in `.git/giddy/`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
 pub struct BranchState {
+    /// format version; states from before versioning are version 0
+    #[serde(default)]
+    pub version: u32,
+    /// branches this one depends on, the base first
     pub deps: IndexSet<String>,
+    /// number of the branch's PR
     pub pr: Option<u32>,
+    /// branch this one was forked from
     pub base: Option<String>,
+    /// commit of `base` this branch was last based on
     pub base_commit: Option<String>,
+    /// the branch is marked as needing an update
     pub dirty: bool,
+    /// placeholder branch without commits of its own, yet
+    pub planned: bool,
+}
+
+impl Default for BranchState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            deps: IndexSet::new(),
+            pr: None,
+            base: None,
+            base_commit: None,
+            dirty: false,
+            planned: false,
+        }
+    }
+}
+
+impl BranchState {
+    /// Upgrades a state read from disk to [`STATE_VERSION`], returning
+    /// whether anything changed.
+    ///
+    /// Fails for states written by a newer giddy, which might hold
+    /// information this one would lose.
+    pub fn migrate(&mut self) -> Result<bool> {
+        if self.version > STATE_VERSION {
+            return Err(Error::StateTooNew(self.version).into());
+        }
+        let original = self.version;
+
+        if self.version == 0 {
+            // unversioned states could lack the base, which is the first dependency
+            if self.base.is_none() {
+                self.base = self.deps.first().cloned();
+            }
+            self.version = 1;
+        }
+
+        Ok(self.version != original)
+    }
 }
 
 impl Repo {
-    pub fn new() -> Repo {
-        let git_dir = Repo::get_git_dir().unwrap();
-        std::fs::create_dir_all(git_dir.join("giddy")).unwrap();
-        Repo { git_dir }
+    /// Opens the repository in the current directory.
+    ///
+    /// Fails with [`Error::NotARepo`] outside of a git repository.
+    pub fn open() -> Result<Repo> {
+        let git_dir = Repo::get_git_dir()?;
+        let config = Config::load_or_default(&git_dir);
+        let repo = Repo {
+            backend: backend::from_config(config.backend, &git_dir),
+            verify_signatures: config.verify_signatures,
+            config,
+            default_branch: OnceCell::new(),
+            forge: OnceCell::new(),
+            remote_branches: OnceCell::new(),
+            git_dir: git_dir.clone(),
+            worktree_git_dir: Repo::get_worktree_git_dir()?,
+            contains_cache: RefCell::default(),
+            merged_cache: RefCell::default(),
+            heads: RefCell::default(),
+            cache: RefCell::new(Cache::load(git_dir.join("giddy").join(".cache"))),
+        };
+        if repo.config.write_commit_graph {
+            repo.ensure_commit_graph();
+        }
+        Ok(repo)
+    }
+
+    /// Writes a commit-graph file if there is none, speeding up ancestry queries.
+    fn ensure_commit_graph(&self) {
+        if self
+            .cmd_output(["config", "--bool", "core.commitGraph"])
+            .is_ok_and(|value| value.trim() == "false")
+        {
+            warning!("`core.commitGraph` is disabled, not writing commit-graph");
+            return;
+        }
+
+        let objects_info = self.git_dir.join("objects").join("info");
+        if objects_info.join("commit-graph").exists() || objects_info.join("commit-graphs").exists()
+        {
+            return;
+        }
+
+        if is_dry_run() {
+            return;
+        }
+
+        info!("giddy: writing commit-graph...");
+        if !self
+            .git()
+            .args(["commit-graph", "write", "--reachable", "--split"])
+            .status()
+            .is_ok_and(|status| status.success())
+        {
+            warning!("writing commit-graph failed");
+        }
+    }
+
+    /// Refuse to rebase onto dependency heads that don't carry a valid signature.
+    pub fn set_verify_signatures(&mut self, verify: bool) {
+        self.verify_signatures = verify;
     }
 
     pub fn graph(&self) -> Result<GraphRepo> {
         GraphRepo::new(self)
     }
 
-    pub fn git(&self) -> std::process::Command {
-        let command = Command::new("git");
-        //command.arg("-C").arg(&self.git_dir);
-        #[expect(clippy::let_and_return)]
-        command
+    pub fn git(&self) -> GitCommand {
+        GitCommand::new()
     }
 
     pub fn git_dir(&self) -> &Utf8Path {
         self.git_dir.as_path()
     }
 
-    pub fn get_git_dir() -> Result<Utf8PathBuf> {
-        let res = Command::new("git")
-            .arg("rev-parse")
-            .arg("--absolute-git-dir")
+    /// Returns the directory giddy keeps its state in, which `giddy init`
+    /// creates (as does saving any state).
+    pub fn giddy_dir(&self) -> Utf8PathBuf {
+        self.git_dir.join("giddy")
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the forge hosting the repository, as configured or detected
+    /// from the default branch's remote.
+    pub fn forge(&self) -> Result<&dyn Forge> {
+        if let Some(forge) = self.forge.get() {
+            return Ok(forge.as_ref());
+        }
+
+        let url = match &self.config.forge_url {
+            Some(url) => url.clone(),
+            None => {
+                let remote = self.upstream_remote(&self.default_branch_name());
+                self.cmd_output(["remote", "get-url", &remote])
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string()
+            }
+        };
+        let forge = forge::from_kind(forge::detect(self.config.forge, &url), &url)?;
+
+        Ok(self.forge.get_or_init(|| forge).as_ref())
+    }
+
+    pub fn verify_commit(&self, commit: &str) -> Result<bool> {
+        Ok(self
+            .git()
+            .args(["verify-commit", commit])
+            .output()?
+            .status
+            .success())
+    }
+
+    /// Returns the value of git config `key`, if set and not empty.
+    fn config_value(&self, key: &str) -> Option<String> {
+        self.cmd_output(["config", key])
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Returns the remote `branch` gets pushed to.
+    ///
+    /// Tries git's `branch.<branch>.pushRemote`, giddy's `branch-remotes`,
+    /// git's `remote.pushDefault` and `branch.<branch>.remote`, and giddy's
+    /// `push-remote`, in this order, then falls back to `origin`.
+    pub fn push_remote(&self, branch: &str) -> String {
+        self.config_value(&format!("branch.{branch}.pushRemote"))
+            .or_else(|| self.config.remote_for(branch).map(str::to_string))
+            .or_else(|| self.config_value("remote.pushDefault"))
+            .or_else(|| self.config_value(&format!("branch.{branch}.remote")))
+            .or_else(|| self.config.push_remote.clone())
+            .unwrap_or_else(|| String::from("origin"))
+    }
+
+    /// Returns the remote `branch` is fetched from (`branch.<branch>.remote`,
+    /// or `origin`), e.g., `upstream` for the default branch of a fork.
+    pub fn upstream_remote(&self, branch: &str) -> String {
+        self.config_value(&format!("branch.{branch}.remote"))
+            .unwrap_or_else(|| String::from("origin"))
+    }
+
+    /// Returns the head to open a PR of `branch` from: `owner:branch` if it
+    /// gets pushed to a different repository than the default branch
+    /// lives in (a fork), `branch` otherwise.
+    pub fn pr_head(&self, branch: &str) -> String {
+        let push_remote = self.push_remote(branch);
+        if push_remote == self.upstream_remote(&self.default_branch_name()) {
+            return branch.to_string();
+        }
+
+        let owner = self
+            .config_value(&format!("remote.{push_remote}.url"))
+            .and_then(|url| forge::parse_repo_url(&url))
+            .map(|(_, owner, _)| owner);
+        match owner {
+            Some(owner) => format!("{owner}:{branch}"),
+            None => branch.to_string(),
+        }
+    }
+
+    pub fn has_upstream(&self, branch: &str) -> bool {
+        self.git()
+            .args([
+                "rev-parse",
+                "--quiet",
+                "--verify",
+                &format!("{branch}@{{upstream}}"),
+            ])
             .output()
-            .expect("failed to execute git");
+            .is_ok_and(|output| output.status.success())
+    }
 
-        let res = String::from_utf8(res.stdout)?;
-        let git_dir = Utf8PathBuf::from(res.trim());
+    /// Force-pushes `branch` (with lease), setting its upstream if missing.
+    pub fn push_branch(&self, branch: &str) -> Result<()> {
+        let remote = self.push_remote(branch);
+        info!("giddy: pushing `{branch}` to `{remote}`...");
+        let mut args = vec!["push", "--quiet", "--force-with-lease"];
+        if !self.has_upstream(branch) {
+            args.push("--set-upstream");
+        }
+        args.extend([remote.as_str(), branch]);
+        self.cmd_check(args)?
+            .true_or(anyhow!("pushing `{branch}` to `{remote}` failed"))
+    }
+
+    pub fn range_diff(&self, old_range: &str, new_range: &str) -> Result<String> {
+        Ok(self
+            .cmd_output(["range-diff", "--no-color", old_range, new_range])?
+            .trim_end()
+            .to_string())
+    }
 
-        Ok(git_dir)
+    /// Returns whether `ancestor` is an ancestor of (or equal to) `commit`.
+    pub fn is_ancestor(&self, ancestor: &str, commit: &str) -> Result<bool> {
+        self.backend.is_ancestor(ancestor, commit)
     }
 
+    /// Points `branch` at `commit`, keeping local changes if it is checked out.
+    pub fn reset_branch(&self, branch: &str, commit: &str) -> Result<()> {
+        let done = if let Some(worktree) = self.other_worktree(branch)? {
+            self.cmd_check([
+                "-C",
+                worktree.as_str(),
+                "reset",
+                "--quiet",
+                "--keep",
+                commit,
+            ])?
+        } else if self.branch_current()?.name() == branch {
+            self.cmd_check(["reset", "--quiet", "--keep", commit])?
+        } else {
+            self.cmd_check(["update-ref", &format!("refs/heads/{branch}"), commit])?
+        };
+
+        done.true_or(anyhow!("resetting `{branch}` to {commit} failed"))
+    }
+
+    /// Moves `branch` forward to `to`, failing if that is not a fast-forward.
+    pub fn fast_forward(&self, branch: &str, to: &str) -> Result<()> {
+        let done = if let Some(worktree) = self.other_worktree(branch)? {
+            self.cmd_check(["-C", worktree.as_str(), "merge", "--ff-only", "--quiet", to])?
+        } else if self.branch_current()?.name() == branch {
+            self.cmd_check(["merge", "--ff-only", "--quiet", to])?
+        } else {
+            self.cmd_check(["fetch", "--quiet", ".", &format!("{to}:{branch}")])?
+        };
+
+        done.true_or(anyhow!("fast-forwarding `{branch}` to `{to}` failed"))
+    }
+
+    /// Returns the path of the worktree `branch` is checked out in, unless
+    /// that is the current worktree.
+    pub fn other_worktree(&self, branch: &str) -> Result<Option<Utf8PathBuf>> {
+        let toplevel = self.cmd_output(["rev-parse", "--show-toplevel"])?;
+        let toplevel = toplevel.trim();
+        let branch_ref = format!("refs/heads/{branch}");
+
+        let mut worktree = None;
+        for line in self.cmd_output_vec(["worktree", "list", "--porcelain"])? {
+            if let Some(path) = line.strip_prefix("worktree ") {
+                worktree = Some(path.to_string());
+            } else if line.strip_prefix("branch ") == Some(branch_ref.as_str()) {
+                return Ok(worktree
+                    .filter(|path| path != toplevel)
+                    .map(Utf8PathBuf::from));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns whether the worktree at `path` has no uncommitted changes to tracked files.
+    pub fn worktree_is_clean(&self, path: &Utf8Path) -> Result<bool> {
+        Ok(self
+            .cmd_output([
+                "-C",
+                path.as_str(),
+                "status",
+                "--porcelain",
+                "--untracked-files=no",
+            ])?
+            .trim()
+            .is_empty())
+    }
+
+    /// Stores `contents` as file `filename` in a commit on top of `parent`
+    /// (if any), and points `refname` at it.
+    pub fn write_ref_file(
+        &self,
+        refname: &str,
+        filename: &str,
+        contents: &str,
+        parent: Option<&str>,
+    ) -> Result<()> {
+        let blob = self.cmd_output_with_input(["hash-object", "-w", "--stdin"], contents)?;
+        let tree =
+            self.cmd_output_with_input(["mktree"], &format!("100644 blob {blob}\t{filename}\n"))?;
+        let mut args = vec!["commit-tree", &tree, "-m", "giddy metadata"];
+        if let Some(parent) = parent {
+            args.extend(["-p", parent]);
+        }
+        let commit = self.cmd_output_with_input(args, "")?;
+        self.cmd_check(["update-ref", refname, &commit])?
+            .true_or(anyhow!("updating `{refname}` failed"))
+    }
+
+    /// Reads file `filename` from the commit `refname` points at.
+    pub fn read_ref_file(&self, refname: &str, filename: &str) -> Result<String> {
+        self.cmd_output_with_input(["show", &format!("{refname}:{filename}")], "")
+            .with_context(|| format!("reading `{filename}` from `{refname}`"))
+    }
+
+    pub fn worktree_status(&self) -> Result<WorktreeStatus> {
+        let mut status = WorktreeStatus::default();
+        for line in self.cmd_output(["status", "--porcelain"])?.lines() {
+            let mut flags = line.chars();
+            let (index, worktree) = (flags.next(), flags.next());
+            if index == Some('?') {
+                status.untracked += 1;
+                continue;
+            }
+            if index.is_some_and(|flag| flag != ' ') {
+                status.staged += 1;
+            }
+            if worktree.is_some_and(|flag| flag != ' ') {
+                status.unstaged += 1;
+            }
+        }
+
+        Ok(status)
+    }
+
+    /// Stashes local changes (not untracked files), returning the stash
+    /// commit, if there were any.
+    pub fn stash_push(&self) -> Result<Option<String>> {
+        let status = self.worktree_status()?;
+        if status.staged + status.unstaged == 0 {
+            return Ok(None);
+        }
+
+        self.cmd_check(["stash", "push", "--quiet", "--message", "giddy: autostash"])?
+            .true_or(anyhow!("stashing local changes failed"))?;
+        info!("giddy: stashed local changes");
+        if is_dry_run() {
+            return Ok(Some("stash@{0}".to_string()));
+        }
+
+        Ok(Some(
+            self.cmd_output(["rev-parse", "stash@{0}"])?
+                .trim()
+                .to_string(),
+        ))
+    }
+
+    /// Restores the changes stashed by [`Repo::stash_push()`], even if
+    /// other stash entries got added meanwhile.
+    pub fn stash_pop(&self, stash: &str) -> Result<()> {
+        let entry = if is_dry_run() {
+            stash.to_string()
+        } else {
+            let index = self
+                .cmd_output_vec(["stash", "list", "--format=%H"])?
+                .iter()
+                .position(|commit| commit == stash)
+                .ok_or_else(|| anyhow!("cannot find stashed changes {stash}"))?;
+            format!("stash@{{{index}}}")
+        };
+
+        self.cmd_check(["stash", "pop", "--quiet", "--index", &entry])?
+            .true_or(anyhow!(
+                "restoring the stashed changes failed, they are still in `git stash list`"
+            ))?;
+        info!("giddy: restored local changes");
+
+        Ok(())
+    }
+
+    /// Returns the number of stash entries created on `branch`.
+    pub fn stash_count(&self, branch: &str) -> Result<usize> {
+        let wip_prefix = format!("WIP on {branch}:");
+        let prefix = format!("On {branch}:");
+        Ok(self
+            .cmd_output_vec(["stash", "list", "--format=%gs"])?
+            .iter()
+            .filter(|subject| subject.starts_with(&wip_prefix) || subject.starts_with(&prefix))
+            .count())
+    }
+
+    /// Returns how many commits `branch` has that `base` doesn't, and how many
+    /// `base` gained since they forked.
+    pub fn ahead_behind(&self, branch: &str, base: &str) -> Result<(usize, usize)> {
+        let counts = self.cmd_output([
+            "rev-list",
+            "--count",
+            "--left-right",
+            &format!("{base}...{branch}"),
+        ])?;
+        let Some((behind, ahead)) = counts.split_whitespace().collect_tuple() else {
+            return Err(anyhow!("cannot compare `{branch}` with `{base}`"));
+        };
+
+        Ok((ahead.parse()?, behind.parse()?))
+    }
+
+    pub fn merge_in_progress(&self) -> bool {
+        self.worktree_git_dir.join("MERGE_HEAD").exists()
+    }
+
+    pub fn rebase_in_progress(&self) -> bool {
+        self.worktree_git_dir.join("rebase-merge").exists()
+            || self.worktree_git_dir.join("rebase-apply").exists()
+    }
+
+    /// Returns whether a rebase is in progress in the worktree at `path`.
+    pub fn rebase_in_progress_at(&self, path: &Utf8Path) -> Result<bool> {
+        let git_dir = self.worktree_git_dir_at(path)?;
+        Ok(git_dir.join("rebase-merge").exists() || git_dir.join("rebase-apply").exists())
+    }
+
+    /// Returns whether a merge is in progress in the worktree at `path`.
+    pub fn merge_in_progress_at(&self, path: &Utf8Path) -> Result<bool> {
+        Ok(self.worktree_git_dir_at(path)?.join("MERGE_HEAD").exists())
+    }
+
+    fn worktree_git_dir_at(&self, path: &Utf8Path) -> Result<Utf8PathBuf> {
+        let git_dir = self.cmd_output(["-C", path.as_str(), "rev-parse", "--absolute-git-dir"])?;
+        Ok(Utf8PathBuf::from(git_dir.trim()))
+    }
+
+    /// Returns the git directory shared by all worktrees, where giddy keeps its state.
+    pub fn get_git_dir() -> Result<Utf8PathBuf> {
+        Self::rev_parse_dir("--git-common-dir")
+    }
+
+    /// Returns the git directory of the current worktree, which differs from
+    /// [`Repo::get_git_dir()`] in linked worktrees.
+    fn get_worktree_git_dir() -> Result<Utf8PathBuf> {
+        Self::rev_parse_dir("--git-dir")
+    }
+
+    fn rev_parse_dir(option: &str) -> Result<Utf8PathBuf> {
+        let output = GitCommand::new()
+            .args(["rev-parse", option])
+            .output()
+            .context("failed to execute git")?;
+        if !output.status.success() {
+            return Err(Error::NotARepo.into());
+        }
+
+        let dir = Utf8PathBuf::from(String::from_utf8(output.stdout)?.trim());
+        if dir.is_absolute() {
+            return Ok(dir);
+        }
+        let cwd = Utf8PathBuf::try_from(std::env::current_dir()?)?;
+        Ok(cwd.join(dir).canonicalize_utf8()?)
+    }
+
+    /// Runs git with `args`, returning its stdout.
+    ///
+    /// Fails with git's error message if it exits unsuccessfully.
     pub fn cmd_output<I, S>(&self, args: I) -> Result<String>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
-        let output = self
-            .git()
+        let args = args.into_iter().collect_vec();
+        let output = self.cmd_output_raw(&args)?;
+        if !output.status.success() {
+            return Err(git_failed(&args, &output.stderr));
+        }
+
+        String::from_utf8(output.stdout)
+            .with_context(|| format!("reading output of `{}`", command_line("git", &args)))
+    }
+
+    /// Runs git with `args`, capturing its stdout and stderr, leaving
+    /// checking its exit status to the caller.
+    fn cmd_output_raw<S: AsRef<OsStr>>(&self, args: &[S]) -> Result<std::process::Output> {
+        self.git()
             .args(args)
             .output()
-            .expect("failed to execute git");
-        let res = String::from_utf8(output.stdout).expect("failed to convert git output into utf8");
+            .context("failed to execute git")
+    }
 
-        Ok(res)
+    /// Runs git with `input` on stdin, returning its (trimmed) stdout on success.
+    pub fn cmd_output_with_input<I, S>(&self, args: I, input: &str) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let args = args.into_iter().collect_vec();
+        let mut child = self
+            .git()
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to execute git")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        trace_output(&output.stdout);
+        if !output.status.success() {
+            return Err(git_failed(&args, &output.stderr));
+        }
+
+        Ok(String::from_utf8(output.stdout)?.trim().to_string())
     }
 
     pub fn cmd_output_vec<I, S>(&self, args: I) -> Result<Vec<String>>
@@ -99,19 +788,108 @@ impl Repo {
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
     {
+        let args = args.into_iter().collect_vec();
+        if skip_in_dry_run("git", &args) {
+            return Ok(true);
+        }
+
+        // this might have changed refs
+        self.refs_changed();
+
         Ok(self.git().args(args).status()?.success())
     }
 
     pub fn branch_current(&self) -> Result<Branch<'_>> {
         let name = self.cmd_output(["branch", "--show-current"])?;
         let name = name.trim();
+        if name.is_empty() {
+            return Err(Error::DetachedHead.into());
+        }
 
         Branch::new(name, self)
     }
 
     pub fn branch_names(&self) -> Result<Vec<String>> {
-        self.cmd_output_vec(["branch", "--format", "%(refname:lstrip=2)"])
-            .context("getting branch names")
+        self.with_heads(|heads| heads.iter().map(|(name, _)| name.clone()).collect())
+    }
+
+    /// Calls `f` with the names and heads of all local branches.
+    ///
+    /// They are read in one go and reused until [`Repo::refs_changed()`].
+    fn with_heads<T>(&self, f: impl FnOnce(&[(String, String)]) -> T) -> Result<T> {
+        if self.heads.borrow().is_none() {
+            let heads = self
+                .backend
+                .branch_heads()
+                .context("getting branch heads")?;
+            *self.heads.borrow_mut() = Some(heads);
+        }
+
+        Ok(f(self.heads.borrow().as_deref().unwrap_or_default()))
+    }
+
+    /// Returns the head of local branch `name` from the snapshot of all
+    /// branch heads, or `None` if `name` is not a local branch.
+    fn cached_head(&self, name: &str) -> Option<String> {
+        let name = name.strip_prefix("refs/heads/").unwrap_or(name);
+        self.with_heads(|heads| {
+            heads
+                .binary_search_by(|(branch, _)| branch.as_str().cmp(name))
+                .ok()
+                .map(|i| heads[i].1.clone())
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Forgets everything cached about refs.
+    ///
+    /// giddy does this itself whenever it changes refs. Call it after
+    /// something else might have, e.g., a hook or another process.
+    pub fn refs_changed(&self) {
+        self.contains_cache.borrow_mut().clear();
+        self.merged_cache.borrow_mut().clear();
+        self.heads.borrow_mut().take();
+    }
+
+    /// Returns whether `name` is a remote-tracking branch (like
+    /// `origin/release`) rather than a local one.
+    ///
+    /// Remote-tracking branches can be dependencies, but giddy never updates
+    /// them.
+    pub fn is_remote_branch(&self, name: &str) -> bool {
+        let remote_branches = self.remote_branches.get_or_init(|| {
+            self.cmd_output_vec([
+                "for-each-ref",
+                "--format=%(refname:lstrip=2)",
+                "refs/remotes",
+            ])
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|name| !name.is_empty() && !name.ends_with("/HEAD"))
+            .collect()
+        });
+
+        remote_branches.iter().any(|remote| remote == name)
+            && self
+                .backend
+                .branch_head(&format!("refs/heads/{name}"))
+                .is_err()
+    }
+
+    /// Returns the branch a PR based on dependency `dep` targets on the
+    /// forge: the branch itself, or for remote-tracking branches like
+    /// `origin/release`, its name on the remote (`release`).
+    pub fn forge_base(&self, dep: &str) -> String {
+        if !self.is_remote_branch(dep) {
+            return dep.to_string();
+        }
+        self.cmd_output_vec(["remote"])
+            .unwrap_or_default()
+            .iter()
+            .find_map(|remote| dep.strip_prefix(remote.as_str())?.strip_prefix('/'))
+            .unwrap_or(dep)
+            .to_string()
     }
 
     pub fn branches(&self) -> Result<Vec<Branch<'_>>> {
@@ -123,19 +901,309 @@ impl Repo {
         Ok(res)
     }
 
-    pub(crate) fn branch_default(&self) -> Result<Branch<'_>> {
+    /// Resolves a branch argument, which may also be a PR reference (`#123` or a PR URL).
+    ///
+    /// PRs are looked up in the branch states first, then through the forge.
+    pub fn resolve_branch<T: AsRef<str>>(&self, name: T) -> Result<String> {
+        let name = name.as_ref();
+        let Some(pr) = forge::parse_pr_ref(name) else {
+            return Ok(name.to_string());
+        };
+
+        for branch in self.branches()? {
+            if branch.state.pr == Some(pr) {
+                return Ok(branch.name().clone());
+            }
+        }
+
+        self.forge()?
+            .pr_head_branch(pr)
+            .with_context(|| format!("resolving `{name}` to a branch"))
+    }
+
+    /// Takes branch `name` out of the dependency graph: its dependents inherit
+    /// its dependencies, and its state gets removed. The git branch stays.
+    pub fn unlink_branch(&self, name: &str) -> Result<()> {
+        // check before touching any state
+        self.check_not_protected(name, "delete")?;
+        self.check_not_in_other_worktree(name, "delete")?;
+        let branch = Branch::new(name, self)?;
+        let deps = branch.deps();
+        let head = branch.head()?;
+
+        for mut dependent in self.branches()? {
+            if !dependent.state.deps.shift_remove(name) {
+                continue;
+            }
+            for dep in &deps {
+                if dep != dependent.name() {
+                    dependent.state.deps.insert(dep.clone());
+                }
+            }
+            // so that the next update can cut off `name`'s commits
+            if dependent.state.base_commit.is_none() && dependent.contains(&head)? {
+                dependent.state.base_commit = Some(head.clone());
+            }
+            dependent.save_state()?;
+            info!(
+                "giddy: `{}` now depends on {}",
+                dependent.name(),
+                dependent
+                    .deps()
+                    .iter()
+                    .map(|dep| format!("`{dep}`"))
+                    .join(", ")
+            );
+        }
+
+        branch.remove_state()
+    }
+
+    /// Renames branch `old` to `new`, moving its state and updating all
+    /// branches that refer to it.
+    pub fn rename_branch(&self, old: &str, new: &str) -> Result<()> {
+        let branch = Branch::new(old, self)?;
+        let has_state = branch.has_state();
+
+        self.cmd_check(["branch", "--move", old, new])?
+            .true_or(anyhow!("renaming branch `{old}` to `{new}` failed"))?;
+
+        if has_state {
+            let mut renamed = Branch {
+                name: new.to_string(),
+                repo: self,
+                state: branch.state.clone(),
+            };
+            renamed.save_state()?;
+            branch.remove_state()?;
+        }
+
+        for mut other in self.branches()? {
+            let mut changed = false;
+            if other.state.deps.contains(old) {
+                other.state.deps = other
+                    .state
+                    .deps
+                    .iter()
+                    .map(|dep| if dep == old { new } else { dep }.to_string())
+                    .collect();
+                changed = true;
+            }
+            if other.state.base.as_deref() == Some(old) {
+                other.state.base = Some(new.to_string());
+                changed = true;
+            }
+            if changed {
+                other.save_state()?;
+                info!("giddy: updated references to `{old}` in `{}`", other.name());
+            }
+        }
+
+        if let Some(pr) = branch.state.pr {
+            warning!("PR #{pr} still has `{old}` as head branch");
+        }
+
+        Ok(())
+    }
+
+    /// Returns the names of branches that have a state file, but no git branch.
+    pub fn orphaned_states(&self) -> Result<Vec<String>> {
+        let branch_names = self.branch_names()?;
+        let mut orphaned = self.state_names()?;
+        orphaned.retain(|name| !branch_names.contains(name));
+
+        Ok(orphaned)
+    }
+
+    /// Returns the names of all branches with a state file, sorted, without
+    /// asking git whether they exist.
+    pub fn state_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let dir = self.giddy_dir();
+        if !dir.exists() {
+            return Ok(names);
+        }
+        for entry in dir.read_dir_utf8()? {
+            let entry = entry?;
+            // giddy's own files start with a dot, which branch names can't
+            if entry.file_name().starts_with('.') || !entry.file_type()?.is_file() {
+                continue;
+            }
+            names.push(entry.file_name().replace("__", "/"));
+        }
+        names.sort();
+
+        Ok(names)
+    }
+
+    /// Returns the cached result of [`Branch::needs_update()`] for branch
+    /// `name` with `deps`, if there is one for their current heads.
+    pub fn cached_needs_update(&self, name: &str, deps: &[String]) -> Option<bool> {
+        let key = self.needs_update_key(name, deps)?;
+        self.cache.borrow().needs_update(&key)
+    }
+
+    fn needs_update_key(&self, name: &str, deps: &[String]) -> Option<String> {
+        self.rev_parse_many(std::iter::once(name).chain(deps.iter().map(String::as_str)))
+            .map(|heads| heads.join(" "))
+    }
+
+    /// Returns the stored state of `name`, without looking at the git branch.
+    pub fn state_of(&self, name: &str) -> Option<BranchState> {
+        let mut branch = Branch {
+            name: name.to_string(),
+            repo: self,
+            state: BranchState::default(),
+        };
+        branch.load_state().ok().map(|_| branch.state)
+    }
+
+    /// Stores `state` as state of `name` (which doesn't need to exist as git
+    /// branch), or removes its state file if `state` is `None`.
+    pub fn set_state_of(&self, name: &str, state: Option<&BranchState>) -> Result<()> {
+        let mut branch = Branch {
+            name: name.to_string(),
+            repo: self,
+            state: state.cloned().unwrap_or_default(),
+        };
+        match state {
+            Some(_) => branch.save_state(),
+            None => branch.remove_state(),
+        }
+    }
+
+    /// Deletes git branch `name`, switching to the default branch if it is checked out.
+    pub fn delete_branch(&self, name: &str) -> Result<()> {
+        self.check_not_protected(name, "delete")?;
+        self.check_not_in_other_worktree(name, "delete")?;
+        if self.branch_current()?.name() == name {
+            self.switch(&self.default_branch_name())?;
+        }
+        self.cmd_check(["branch", "--quiet", "-D", name])?
+            .true_or(anyhow!("deleting branch `{name}` failed"))
+    }
+
+    /// Returns whether giddy must leave `branch` alone: it is the default
+    /// branch or matches `protected-branches`.
+    pub fn is_protected(&self, branch: &str) -> bool {
+        branch == self.default_branch_name() || self.config.is_protected(branch)
+    }
+
+    /// Fails if `branch` is protected, as giddy won't `action` it then.
+    fn check_not_protected(&self, branch: &str, action: &str) -> Result<()> {
+        if self.is_protected(branch) {
+            return Err(Error::Protected {
+                branch: branch.to_string(),
+                action: action.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub fn branch_default(&self) -> Result<Branch<'_>> {
         Branch::new(self.default_branch_name(), self)
     }
 
-    pub(crate) fn default_branch_name(&self) -> String {
-        // TODO: get actual default branch name
-        String::from("main")
+    pub fn default_branch_name(&self) -> String {
+        self.default_branch
+            .get_or_init(|| self.detect_default_branch())
+            .clone()
     }
 
-    pub(crate) fn branch_create(&self, name: &str) -> Result<Branch<'_>> {
-        self.cmd_check(["switch", "--create", name])?
+    /// Figures out the default branch, trying (in this order) the cached result,
+    /// `origin/HEAD`, `upstream/HEAD`, `init.defaultBranch` and a few common names.
+    fn detect_default_branch(&self) -> String {
+        if let Some(name) = &self.config.default_branch {
+            return name.clone();
+        }
+
+        let cache_file = self.giddy_dir().join(".default-branch");
+        let exists = |name: &str| {
+            !name.is_empty()
+                && self
+                    .git()
+                    .args([
+                        "show-ref",
+                        "--verify",
+                        "--quiet",
+                        &format!("refs/heads/{name}"),
+                    ])
+                    .status()
+                    .is_ok_and(|status| status.success())
+        };
+
+        if let Ok(cached) = std::fs::read_to_string(&cache_file) {
+            if exists(cached.trim()) {
+                return cached.trim().to_string();
+            }
+        }
+
+        // forks usually have the default branch on `upstream`
+        let remote_heads = ["origin", "upstream"].map(|remote| {
+            self.cmd_output([
+                "symbolic-ref",
+                "--quiet",
+                "--short",
+                &format!("refs/remotes/{remote}/HEAD"),
+            ])
+            .unwrap_or_default()
+            .trim()
+            .strip_prefix(&format!("{remote}/"))
+            .unwrap_or_default()
+            .to_string()
+        });
+        let init_default = self
+            .cmd_output(["config", "init.defaultBranch"])
+            .unwrap_or_default();
+        let candidates = [
+            remote_heads[0].as_str(),
+            remote_heads[1].as_str(),
+            init_default.trim(),
+            "main",
+            "master",
+            "trunk",
+        ];
+
+        let found = candidates.into_iter().find(|name| exists(name));
+        match found {
+            Some(name) => {
+                // only a cache, so failing to write it doesn't matter; and
+                // read-only commands must not create the giddy directory
+                if self.giddy_dir().is_dir() {
+                    std::fs::write(&cache_file, name).ok();
+                }
+                name.to_string()
+            }
+            None => String::from("main"),
+        }
+    }
+
+    /// Creates branch `name` at `base` and switches to it.
+    pub fn branch_create(&self, name: &str, base: &str) -> Result<Branch<'_>> {
+        self.cmd_check(["switch", "--create", name, base])?
             .true_or(anyhow!("creating branch failed"))?;
-        Branch::new_with_base(name, self.branch_current()?.name(), self)
+        Branch::new_with_base(name, base, self)
+    }
+
+    /// Fails if `branch` is checked out in another worktree, as git refuses
+    /// to `action` it then.
+    fn check_not_in_other_worktree(&self, branch: &str, action: &str) -> Result<()> {
+        match self.other_worktree(branch)? {
+            Some(worktree) => Err(Error::CheckedOutElsewhere {
+                branch: branch.to_string(),
+                action: action.to_string(),
+                worktree,
+            }
+            .into()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn switch(&self, name: &str) -> Result<()> {
+        self.check_not_in_other_worktree(name, "switch to")?;
+        self.cmd_check(["switch", name])?
+            .true_or(anyhow!("switching to branch `{name}` failed"))
     }
 
     pub fn fork_point<T: AsRef<str>, S: AsRef<str>>(
@@ -145,7 +1213,24 @@ impl Repo {
     ) -> Result<Option<String>> {
         let name: &str = name.as_ref();
         let other: &str = base.as_ref();
-        let res = self.cmd_output(["merge-base", "--fork-point", other, name])?;
+
+        let key = self
+            .rev_parse_many([name, other])
+            .map(|heads| heads.join(" "));
+        if let Some(fork_point) = key
+            .as_ref()
+            .and_then(|key| self.cache.borrow().fork_point(key))
+        {
+            return Ok(fork_point);
+        }
+
+        // exits with 1 if there is none
+        let args = ["merge-base", "--fork-point", other, name];
+        let output = self.cmd_output_raw(&args)?;
+        if !(output.status.success() || output.stderr.is_empty()) {
+            return Err(git_failed(&args, &output.stderr));
+        }
+        let res = String::from_utf8(output.stdout)?;
         let res = res.trim();
 
         let fork_point = if res.is_empty() {
@@ -154,9 +1239,37 @@ impl Repo {
             Some(res.into())
         };
 
+        if let Some(key) = key {
+            self.cache
+                .borrow_mut()
+                .set_fork_point(key, fork_point.clone());
+        }
+
         Ok(fork_point)
     }
 
+    /// Returns the commit ids of all `revs`, using a single git invocation.
+    ///
+    /// Returns `None` if any of them cannot be resolved.
+    pub fn rev_parse_many<I, S>(&self, revs: I) -> Option<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let revs = revs
+            .into_iter()
+            .map(|rev| rev.as_ref().to_string())
+            .collect_vec();
+        if let Some(heads) = revs.iter().map(|rev| self.cached_head(rev)).collect() {
+            return Some(heads);
+        }
+        let output = self.git().arg("rev-parse").args(&revs).output().ok()?;
+        let heads = String::from_utf8(output.stdout).ok()?;
+        let heads = heads.lines().map(|line| line.to_string()).collect_vec();
+
+        (output.status.success() && heads.len() == revs.len()).then_some(heads)
+    }
+
     pub fn get_base_branch<T: AsRef<str>>(&self, branch: T) -> Result<String> {
         let default_branch = self.default_branch_name();
         let branch = branch.as_ref();
@@ -166,7 +1279,12 @@ impl Repo {
             return Ok(default_branch);
         }
 
-        let fork_point = self.fork_point(branch, &default_branch)?.ok_or_else(||anyhow!("cannot determine fork point between `{branch}` and the default branch `{default_branch}`. has it been merged?"))?;
+        let fork_point =
+            self.fork_point(branch, &default_branch)?
+                .ok_or_else(|| Error::ForkPointUnknown {
+                    branch: branch.to_string(),
+                    base: default_branch.clone(),
+                })?;
 
         let mut log = self.cmd_output_vec([
             "log",
@@ -193,11 +1311,10 @@ impl Repo {
     }
 
     pub fn branch_head<T: AsRef<str>>(&self, name: T) -> Result<String> {
-        let name: &str = name.as_ref();
-        let res = self.cmd_output(["rev-parse", name])?;
-        let res = res.trim();
-
-        Ok(res.into())
+        if let Some(head) = self.cached_head(name.as_ref()) {
+            return Ok(head);
+        }
+        self.backend.branch_head(name.as_ref())
     }
 
     #[expect(unused)]
@@ -210,51 +1327,184 @@ impl Repo {
             .true_or(anyhow!("error checking out commit"))
     }
 
-    pub fn merge_base<T: AsRef<str>, S: AsRef<str>>(&self, branch: T, other: S) -> Result<String> {
-        let branch: &str = branch.as_ref();
-        let other: &str = other.as_ref();
-        let res = self.cmd_output(["merge-base", other, branch])?;
-        let res = res.trim();
+    pub fn merge_base<T: AsRef<str>, S: AsRef<str>>(
+        &self,
+        branch: T,
+        other: S,
+    ) -> Result<Option<String>> {
+        self.backend.merge_base(other.as_ref(), branch.as_ref())
+    }
+
+    /// Rebases the commits of `branch` after `old` onto `new`, returning
+    /// whether that succeeded.
+    pub fn rebase_onto(&self, new: &str, old: &str, branch: &str) -> Result<bool> {
+        let flags = &self.rebase_flags();
+        if skip_in_dry_run(
+            "git",
+            ["rebase"]
+                .into_iter()
+                .chain(flags.iter().map(String::as_str))
+                .chain(["--onto", new, old, branch]),
+        ) {
+            return Ok(true);
+        }
+
+        // this changes refs
+        self.refs_changed();
+
+        self.backend.rebase_onto(new, old, branch, flags)
+    }
 
-        Ok(res.into())
+    /// Returns the configured extra `git rebase` arguments, plus `--quiet`
+    /// if giddy is quiet.
+    fn rebase_flags(&self) -> Vec<String> {
+        let mut flags = self.config.rebase_flags.clone();
+        if output::quiet_level() > 0 {
+            flags.push("--quiet".to_string());
+        }
+        flags
     }
 
     pub fn contains<T: AsRef<str>, S: AsRef<str>>(&self, branch: T, contains: S) -> Result<bool> {
         let branch: &str = branch.as_ref();
         let contains: &str = contains.as_ref();
 
+        if let Some(branches) = self.contains_cache.borrow().get(contains) {
+            return Ok(branches.iter().any(|b| b == branch));
+        }
+
+        // list all branches at once, so checking further branches is free
         let results = self
             .cmd_output_vec([
                 "branch",
-                "--format=%(refname)",
+                "--format=%(refname:lstrip=2)",
                 "--contains",
                 contains,
-                branch,
             ])
             .with_context(|| format!("checking if `{branch}` contains `{contains}`"))?;
+        let res = results.iter().any(|b| b == branch);
+        self.contains_cache
+            .borrow_mut()
+            .insert(contains.to_string(), results);
 
-        Ok(results
-            .first()
-            .is_some_and(|first| first == &format!("refs/heads/{branch}")))
+        Ok(res)
     }
 
     pub fn merged<T: AsRef<str>, S: AsRef<str>>(&self, branch: T, has_merged: S) -> Result<bool> {
         let branch: &str = branch.as_ref();
         let has_merged: &str = has_merged.as_ref();
 
+        if let Some(branches) = self.merged_cache.borrow().get(branch) {
+            return Ok(branches.iter().any(|b| b == has_merged));
+        }
+
         let results = self
-            .cmd_output_vec([
-                "branch",
-                "--format=%(refname)",
-                "--merged",
-                branch,
-                has_merged,
-            ])
+            .cmd_output_vec(["branch", "--format=%(refname:lstrip=2)", "--merged", branch])
             .with_context(|| format!("checking if `{branch}` has merged `{has_merged}`"))?;
+        let res = results.iter().any(|b| b == has_merged);
+        self.merged_cache
+            .borrow_mut()
+            .insert(branch.to_string(), results);
 
-        Ok(results
-            .first()
-            .is_some_and(|first| first == &format!("refs/heads/{has_merged}")))
+        Ok(res)
+    }
+
+    /// Returns whether the changes of `branch` landed in `target` without
+    /// merging it, i.e., squash-merged, rebased or cherry-picked.
+    ///
+    /// Compares patch-ids, like `git cherry`: of each commit of `branch`, and
+    /// of all its changes squashed into one diff. Results get cached by the
+    /// heads of both.
+    pub fn squash_merged<T: AsRef<str>, S: AsRef<str>>(
+        &self,
+        target: T,
+        branch: S,
+    ) -> Result<bool> {
+        let target: &str = target.as_ref();
+        let branch: &str = branch.as_ref();
+
+        let key = self
+            .rev_parse_many([target, branch])
+            .map(|heads| heads.join(" "));
+        if let Some(squash_merged) = key
+            .as_ref()
+            .and_then(|key| self.cache.borrow().squash_merged(key))
+        {
+            return Ok(squash_merged);
+        }
+
+        let squash_merged = self.squash_merged_uncached(target, branch)?;
+        if let Some(key) = key {
+            self.cache
+                .borrow_mut()
+                .set_squash_merged(key, squash_merged);
+        }
+
+        Ok(squash_merged)
+    }
+
+    fn squash_merged_uncached(&self, target: &str, branch: &str) -> Result<bool> {
+        let Some(base) = self.merge_base(branch, target)? else {
+            return Ok(false);
+        };
+        if base == self.branch_head(branch)? {
+            return Ok(false);
+        }
+
+        // `-` marks commits that have an equivalent in `target`
+        let lines = self.cmd_output_vec(["cherry", target, branch, &base])?;
+        if !lines.is_empty() && lines.iter().all(|line| line.starts_with('-')) {
+            return Ok(true);
+        }
+
+        let Some(squashed) = self
+            .patch_ids(["diff", "--no-color", "--no-ext-diff", &base, branch])?
+            .pop()
+        else {
+            return Ok(false);
+        };
+        let landed = self.patch_ids([
+            "log",
+            "-p",
+            "--no-merges",
+            "--no-color",
+            "--no-ext-diff",
+            &format!("{base}..{target}"),
+        ])?;
+
+        Ok(landed.contains(&squashed))
+    }
+
+    /// Returns the patch-ids of the patches `git <args>` prints.
+    fn patch_ids<S: AsRef<OsStr>>(&self, args: impl IntoIterator<Item = S>) -> Result<Vec<String>> {
+        let args = args.into_iter().collect_vec();
+        let mut patches = self
+            .git()
+            .args(&args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("failed to execute git")?;
+        let stdout = patches.stdout.take().context("capturing git output")?;
+        let output = self
+            .git()
+            .args(["patch-id", "--stable"])
+            .stdin(stdout)
+            .output()
+            .context("failed to execute git")?;
+        let status = patches.wait()?;
+        if !status.success() {
+            return Err(git_failed(&args, &[]));
+        }
+        if !output.status.success() {
+            return Err(git_failed(&["patch-id", "--stable"], &output.stderr));
+        }
+
+        // `<patch-id> <commit id>` per patch
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect())
     }
 
     pub fn equal<T: AsRef<str>, S: AsRef<str>>(&self, branch: T, other: S) -> Result<bool> {
@@ -265,6 +1515,17 @@ impl Repo {
     }
 }
 
+impl Drop for Repo {
+    fn drop(&mut self) {
+        // the cache is only an optimization, so errors don't matter; and it
+        // doesn't get to create the giddy directory in untracked repositories,
+        // or to write anything in a dry run
+        if !is_dry_run() && self.giddy_dir().is_dir() {
+            self.cache.borrow_mut().save().ok();
+        }
+    }
+}
+
 impl<'a> Branch<'a> {
     pub fn new_with_base<T: AsRef<str>, S: AsRef<str>>(
         name: T,
@@ -292,13 +1553,29 @@ impl<'a> Branch<'a> {
             state: Default::default(),
         };
 
-        res.load_state().ok();
-        if res.state.base.is_none() && res.name != repo.default_branch_name() {
+        // an unreadable state must not get overwritten with defaults
+        if res.has_state() {
+            res.load_state()?;
+        }
+        if res.state.planned && res.has_own_commits()? {
+            // commits have landed, so this is a real branch now (which only
+            // commands that change something save, see `save_landed()`)
+            res.state.planned = false;
+        }
+        if res.state.base.is_none()
+            && res.name != repo.default_branch_name()
+            && !repo.is_remote_branch(&res.name)
+        {
             res.state.base = Some(repo.default_branch_name());
         }
         if res.state.base_commit.is_none() {
-            if let Some(ref base) = res.state.base {
-                let base_head = repo.branch_head(base)?;
+            // the base branch might be gone
+            if let Some(base_head) = res
+                .state
+                .base
+                .as_ref()
+                .and_then(|base| repo.branch_head(base).ok())
+            {
                 if res.contains(&base_head)? {
                     res.state.base_commit = Some(base_head);
                 }
@@ -319,9 +1596,11 @@ impl<'a> Branch<'a> {
         self.repo.equal(&self.name, other)
     }
 
+    /// Returns whether this branch got merged into `other`, also through a
+    /// squash merge or rebase.
     pub fn merged_into<T: AsRef<str>>(&self, other: T) -> Result<bool> {
         let other = other.as_ref();
-        self.repo.merged(other, &self.name)
+        Ok(self.repo.merged(other, &self.name)? || self.repo.squash_merged(other, &self.name)?)
     }
 
     pub fn merged(&self) -> Result<bool> {
@@ -338,34 +1617,126 @@ impl<'a> Branch<'a> {
         self.repo.fork_point(self.name(), other.as_ref())
     }
 
-    #[expect(unused)]
-    pub fn merge_base<T: AsRef<str>>(&self, other: T) -> Result<String> {
+    pub fn merge_base<T: AsRef<str>>(&self, other: T) -> Result<Option<String>> {
         self.repo.merge_base(self.name(), other)
     }
 
+    /// Returns whether this branch has commits on top of its base.
+    pub fn has_own_commits(&self) -> Result<bool> {
+        match &self.state.base_commit {
+            Some(base_commit) => Ok(&self.head()? != base_commit),
+            None => Ok(!self.commit_subjects()?.is_empty()),
+        }
+    }
+
+    /// Returns the branch description (`git branch --edit-description`), if any.
+    pub fn description(&self) -> Result<Option<String>> {
+        Ok(self
+            .repo
+            .config_value(&format!("branch.{}.description", self.name)))
+    }
+
+    /// Returns the revisions selecting the commits of this branch itself, for
+    /// `git log` and `git rev-list`.
+    ///
+    /// Those are the ones since the recorded base commit, or, without one, the
+    /// ones not on any of its dependencies.
+    pub fn own_commit_revs(&self) -> Vec<String> {
+        let mut revs = vec![self.name.clone()];
+        match &self.state.base_commit {
+            Some(base_commit) => revs.push(format!("^{base_commit}")),
+            None => revs.extend(self.deps().iter().map(|dep| format!("^{dep}"))),
+        }
+        revs
+    }
+
+    /// Returns the subjects of the commits on this branch that are not on any of
+    /// its dependencies, oldest first.
+    pub fn commit_subjects(&self) -> Result<Vec<String>> {
+        let mut args = vec![
+            "log".to_string(),
+            "--reverse".to_string(),
+            "--format=%s".to_string(),
+            self.name.clone(),
+        ];
+        args.extend(self.deps().iter().map(|dep| format!("^{dep}")));
+
+        self.repo.cmd_output_vec(args)
+    }
+
     fn state_file(&self) -> Utf8PathBuf {
         let slug = self.name.replace("/", "__");
-        self.repo.git_dir().join("giddy").join(slug)
+        self.repo.giddy_dir().join(slug)
     }
 
     pub fn load_state(&mut self) -> Result<()> {
         let state_file = self.state_file();
-        let state: BranchState = read_from_file(state_file)
+        let mut state: BranchState = read_from_file(state_file)
+            .with_context(|| anyhow!("reading state file for branch `{}`", self.name))?;
+        let migrated = state
+            .migrate()
             .with_context(|| anyhow!("reading state file for branch `{}`", self.name))?;
         self.state = state;
+
+        // (dry-run only reads)
+        if migrated && !is_dry_run() {
+            write_to_file(self.state_file(), &self.state)?;
+        }
         Ok(())
     }
 
+    pub fn has_state(&self) -> bool {
+        self.state_file().is_file()
+    }
+
     pub fn save_state(&mut self) -> Result<()> {
+        self.check_no_new_deps()?;
+        if is_dry_run() {
+            println!(
+                "giddy: would save state of branch `{}`: {}",
+                self.name,
+                serde_json::to_string(&self.state)?
+            );
+            return Ok(());
+        }
+
         let state_file = self.state_file();
         write_to_file(state_file, &self.state)?;
         Ok(())
     }
 
+    /// Fails if this branch is protected and got dependencies it didn't have
+    /// (removing some is fine, to repair mistakes).
+    fn check_no_new_deps(&self) -> Result<()> {
+        if !self.repo.is_protected(self.name()) {
+            return Ok(());
+        }
+        let saved = self.repo.state_of(self.name()).unwrap_or_default().deps;
+        if self.state.deps.iter().all(|dep| saved.contains(dep)) {
+            return Ok(());
+        }
+
+        self.repo.check_not_protected(self.name(), "reparent")
+    }
+
+    pub fn remove_state(&self) -> Result<()> {
+        if is_dry_run() {
+            println!("giddy: would remove state of branch `{}`", self.name);
+            return Ok(());
+        }
+
+        let state_file = self.state_file();
+        if state_file.exists() {
+            std::fs::remove_file(&state_file)
+                .with_context(|| anyhow!("removing state file for branch `{}`", self.name))?;
+        }
+        Ok(())
+    }
+
     pub fn deps(&self) -> Vec<String> {
         if self.state.deps.is_empty() {
             let default_branch_name = self.repo.default_branch_name();
-            if self.name == default_branch_name {
+            if self.name == default_branch_name || self.repo.is_remote_branch(&self.name) {
                 Vec::new()
             } else {
                 vec![self.repo.default_branch_name()]
@@ -382,7 +1753,33 @@ impl<'a> Branch<'a> {
     }
 
     pub fn needs_update(&self) -> Result<bool> {
-        for dep in self.state.deps.iter() {
+        // untracked branches don't have dependencies to be behind of
+        if !self.has_state() {
+            return Ok(false);
+        }
+
+        let deps = self.deps();
+        let key = self.repo.needs_update_key(&self.name, &deps);
+        if let Some(needs_update) = key
+            .as_ref()
+            .and_then(|key| self.repo.cache.borrow().needs_update(key))
+        {
+            return Ok(needs_update);
+        }
+
+        let needs_update = self.needs_update_uncached(&deps)?;
+        if let Some(key) = key {
+            self.repo
+                .cache
+                .borrow_mut()
+                .set_needs_update(key, needs_update);
+        }
+
+        Ok(needs_update)
+    }
+
+    fn needs_update_uncached(&self, deps: &[String]) -> Result<bool> {
+        for dep in deps {
             let fork_point = self.fork_point(dep)?;
             if let Some(fork_point) = fork_point {
                 //println!("fork point of {} on {} is {}", self.name(), dep, fork_point);
@@ -401,16 +1798,58 @@ impl<'a> Branch<'a> {
         Ok(false)
     }
 
+    /// Saves that this planned branch got commits, which [`Branch::new()`]
+    /// only notes in memory.
+    pub fn save_landed(&mut self) -> Result<()> {
+        let saved_planned = self
+            .repo
+            .state_of(&self.name)
+            .is_some_and(|state| state.planned);
+        if saved_planned && !self.state.planned {
+            self.save_state()?;
+        }
+        Ok(())
+    }
+
     pub fn update(&mut self) -> Result<()> {
-        let mut deps = self.deps();
+        let _span = tracing::info_span!("update", branch = %self.name).entered();
+        self.save_landed()?;
+        if self.state.planned {
+            info!("branch {} is planned, skipping.", self.name());
+            return Ok(());
+        }
+
+        let deps = self.deps();
+        if !deps.is_empty() {
+            self.repo.check_not_protected(self.name(), "rebase")?;
+        }
         if deps.is_empty() {
-            println!(
+            info!(
                 "branch {} does not have deps, no update needed.",
                 self.name()
             );
             return Ok(());
         }
 
+        let name = self.name.clone();
+        let old_head = self.head()?;
+        let mut env = hooks::Env {
+            branch: &name,
+            base: deps.first().map(String::as_str),
+            old_head: Some(&old_head),
+            new_head: None,
+        };
+        hooks::run(self.repo, Hook::PreUpdate, &env)?;
+
+        self.update_from_deps(deps.clone())?;
+
+        let new_head = self.head()?;
+        env.new_head = Some(&new_head);
+        hooks::run(self.repo, Hook::PostUpdate, &env)
+    }
+
+    /// Rebases this branch onto its single dependency, or merges several.
+    fn update_from_deps(&mut self, mut deps: Vec<String>) -> Result<()> {
         if deps.len() > 1 {
             deps.retain(|dep| dep != &self.repo.default_branch_name());
             self.state
@@ -420,16 +1859,13 @@ impl<'a> Branch<'a> {
         }
 
         if deps.len() > 1 {
-            return Err(anyhow!(
-                "branch `{}` has more than one dependency, which is currently unsupported.",
-                self.name()
-            ));
+            return self.merge_deps(&deps);
         }
 
         let dep = deps.first().unwrap();
         if let Some(previous) = self.state.base.as_ref().cloned() {
             if dep != &previous {
-                println!(
+                info!(
                     "branch `{}`: rebasing from `{}` onto `{}`...",
                     self.name, previous, dep
                 );
@@ -437,51 +1873,234 @@ impl<'a> Branch<'a> {
                 let previous = self.state.base_commit.as_ref().unwrap_or(&previous).clone();
 
                 self.rebase_onto(&previous, dep)?;
-                self.state.base = Some(dep.clone());
-                self.state.base_commit = Some(self.repo.branch_head(dep)?);
-                self.state.dirty = false;
-                self.save_state()?;
-
-                return Ok(());
+                return self.finish_rebase();
             }
         }
 
         let dep_head = self.repo.branch_head(dep)?;
         let branch_head = self.head()?;
 
-        let skip_update = (branch_head == dep_head)
-            || self.repo.contains(dep, &self.name)?
-            || self.repo.merged(dep, &self.name)?;
-
-        if skip_update {
-            println!("branch {}: no update needed.", self.name());
-        } else if let Some(old_base) = self.state.base_commit.as_ref().cloned() {
-            println!("rebasing branch `{}` on `{dep}`...", self.name());
+        // no commits of our own on top of `dep`
+        let behind = self.repo.contains(dep, &self.name)? || self.repo.merged(dep, &self.name)?;
+
+        // already rebased onto the current head of `dep`, as `needs_update()`
+        // would say; rebasing again would be a no-op that still checks out
+        // the branch (and fails on local changes)
+        let on_dep = self.state.base_commit.as_ref() == Some(&dep_head)
+            && self.repo.is_ancestor(&dep_head, &branch_head)?;
+
+        if branch_head == dep_head || on_dep {
+            info!("branch {}: no update needed.", self.name());
+        } else if behind {
+            self.check_dep_signature(dep)?;
+            self.repo.fast_forward(self.name(), dep)?;
+            self.state.base_commit = Some(dep_head);
+            self.save_state()?;
+            info!("branch `{}`: fast-forwarded to `{dep}`.", self.name());
+        } else if let Some(old_base) = self
+            .state
+            .base_commit
+            .clone()
+            .map_or_else(|| self.fork_point(dep), |commit| Ok(Some(commit)))?
+        {
+            info!("rebasing branch `{}` on `{dep}`...", self.name());
             self.rebase_onto(&old_base, dep)?;
+            self.state.base_commit = Some(dep_head);
+            self.save_state()?;
         } else {
-            return Err(anyhow!(
-                "unable to determine fork point between `{}` and `{}`!",
-                self.name(),
-                dep
-            ));
+            return Err(Error::ForkPointUnknown {
+                branch: self.name().clone(),
+                base: dep.to_string(),
+            }
+            .into());
         }
 
         Ok(())
     }
 
-    fn rebase_on(&self, dep: &str) -> Result<()> {
-        self.repo.cmd_check(["rebase", dep, self.name()])?;
-        Ok(())
+    /// Removes the commits of (former dependency) `dep` from this branch, by
+    /// rebasing the remaining commits onto the current first dependency.
+    pub fn drop_commits_of(&mut self, dep: &str) -> Result<()> {
+        let new_base =
+            self.deps().first().cloned().ok_or_else(|| {
+                anyhow!("branch `{}` has no dependency to rebase onto", self.name())
+            })?;
+
+        if self.repo.is_ancestor(dep, &self.name)? {
+            info!(
+                "branch `{}`: dropping commits of `{dep}`, rebasing onto `{new_base}`...",
+                self.name()
+            );
+            self.rebase_onto(dep, &new_base)?;
+        } else {
+            // `dep` moved since, so its commits can't be cut off as a range
+            if !ui::is_interactive() {
+                return Err(Error::PromptRequired(format!(
+                    "commits of `{dep}` are interleaved with those of `{}`",
+                    self.name()
+                ))
+                .into());
+            }
+            info!(
+                "branch `{}`: commits of `{dep}` are interleaved with the branch's own, drop them in the interactive rebase",
+                self.name()
+            );
+            self.repo.switch(&self.name)?;
+            self.repo
+                .cmd_check(["rebase", "--interactive", &new_base])?
+                .true_or(anyhow!("rebasing `{}` failed", self.name()))?;
+        }
+
+        self.finish_rebase()
     }
 
-    fn rebase_onto(&mut self, old: &str, new: &str) -> Result<()> {
+    /// Points this branch's open PR (if any) at `base`.
+    ///
+    /// Failures only cause a warning, as the branch itself is fine.
+    pub fn retarget_pr(&self, base: &str) {
+        let Some(pr) = self.state.pr else {
+            return;
+        };
+
+        let result = self.repo.forge().and_then(|forge| {
+            let view = forge.pr_view(pr)?;
+            if view.state == "OPEN" && view.base_ref_name != base {
+                forge.set_pr_base(pr, base)?;
+                info!("branch `{}`: retargeted PR #{pr} to `{base}`", self.name());
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            warning!(
+                "branch `{}`: cannot retarget PR #{pr} to `{base}`: {e:#}",
+                self.name()
+            );
+        }
+    }
+
+    #[expect(unused)]
+    fn rebase_on(&self, dep: &str) -> Result<()> {
         self.repo
-            .cmd_check(["rebase", "--onto", new, old, self.name()])?;
+            .cmd_check(["rebase", dep, self.name()])?
+            .true_or(anyhow!("rebasing `{}` on `{dep}` failed", self.name()))
+    }
+
+    /// Fails if signatures get verified and the head of `dep` has no valid
+    /// one, as this branch is about to take it in.
+    fn check_dep_signature(&self, dep: &str) -> Result<()> {
+        if !self.repo.verify_signatures {
+            return Ok(());
+        }
+
+        let head = self.repo.branch_head(dep)?;
+        self.repo.verify_commit(&head)?.true_or(anyhow!(
+            "refusing to update `{}` from `{dep}`: head commit {head} has no valid signature",
+            self.name()
+        ))
+    }
+
+    pub fn rebase_onto(&mut self, old: &str, new: &str) -> Result<()> {
+        self.check_dep_signature(new)?;
+
+        // git refuses to rebase a branch that is checked out in another worktree
+        if let Some(worktree) = self.repo.other_worktree(self.name())? {
+            info!(
+                "branch `{}` is checked out in worktree `{worktree}`, rebasing there...",
+                self.name()
+            );
+            let done = self.repo.cmd_check(
+                ["-C", worktree.as_str(), "rebase"]
+                    .into_iter()
+                    .chain(self.repo.rebase_flags().iter().map(String::as_str))
+                    .chain(["--onto", new, old]),
+            )?;
+            if !done && self.repo.rebase_in_progress_at(&worktree)? {
+                return Err(self.rebase_conflict(new));
+            }
+            return done.true_or(anyhow!(
+                "rebasing `{}` in worktree `{worktree}` failed",
+                self.name()
+            ));
+        }
+
+        let done = self.repo.rebase_onto(new, old, self.name())?;
+        if !done && self.repo.rebase_in_progress() {
+            return Err(self.rebase_conflict(new));
+        }
+        done.true_or(anyhow!("rebasing `{}` onto `{new}` failed", self.name()))
+    }
+
+    fn rebase_conflict(&self, onto: &str) -> anyhow::Error {
+        Error::RebaseConflict {
+            branch: self.name().clone(),
+            onto: onto.to_string(),
+        }
+        .into()
+    }
+
+    /// Updates a branch with multiple dependencies by merging each of them
+    /// that it doesn't contain, yet.
+    ///
+    /// Rebasing onto more than one branch isn't possible, so such branches get
+    /// merge commits instead.
+    fn merge_deps(&mut self, deps: &[String]) -> Result<()> {
+        let mut missing = Vec::new();
+        for dep in deps {
+            if !self.contains(self.repo.branch_head(dep)?)? {
+                missing.push(dep.as_str());
+            }
+        }
+        if missing.is_empty() {
+            info!("branch {}: no update needed.", self.name());
+            return Ok(());
+        }
+        for dep in &missing {
+            self.check_dep_signature(dep)?;
+        }
+
+        let worktree = self.repo.other_worktree(self.name())?;
+        if worktree.is_none() && self.repo.branch_current()?.name() != self.name() {
+            self.repo.switch(self.name())?;
+        }
+
+        for dep in missing {
+            info!("branch `{}`: merging `{dep}`...", self.name());
+            let message = format!("Merge branch '{dep}' into {}", self.name());
+            let mut args = Vec::new();
+            if let Some(worktree) = &worktree {
+                args.extend(["-C", worktree.as_str()]);
+            }
+            args.extend(["merge", "--no-edit", "-m", &message, dep]);
+            self.repo
+                .cmd_check(args)?
+                .true_or(anyhow!("merging `{dep}` into `{}` failed", self.name()))?;
+        }
+
+        self.state.dirty = false;
+        self.save_state()
+    }
+
+    /// Records that this branch got rebased onto its dependency, for rebases
+    /// that were finished outside of `update()` (e.g., after resolving conflicts).
+    pub fn finish_rebase(&mut self) -> Result<()> {
+        let Some(dep) = self.deps().into_iter().next() else {
+            return Ok(());
+        };
+        let reparented = self.state.base.as_ref().is_some_and(|base| base != &dep);
+
+        self.state.base = Some(dep.clone());
+        self.state.base_commit = Some(self.repo.branch_head(&dep)?);
+        self.state.dirty = false;
+        self.save_state()?;
+        if reparented {
+            self.retarget_pr(&self.repo.forge_base(&dep));
+        }
+
         Ok(())
     }
 }
 
-trait TrueOr {
+pub trait TrueOr {
     fn true_or(self, error: anyhow::Error) -> Result<()>;
 }
 
@@ -495,7 +2114,7 @@ impl TrueOr for bool {
     }
 }
 
-fn read_from_file<P: AsRef<Path>, T: DeserializeOwned>(path: P) -> Result<T> {
+pub fn read_from_file<P: AsRef<Path>, T: DeserializeOwned>(path: P) -> Result<T> {
     // Open the file in read-only mode with buffer.
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -507,7 +2126,10 @@ fn read_from_file<P: AsRef<Path>, T: DeserializeOwned>(path: P) -> Result<T> {
     Ok(u)
 }
 
-fn write_to_file<P: AsRef<Path>, T: Serialize>(path: P, val: &T) -> Result<()> {
+pub fn write_to_file<P: AsRef<Path>, T: Serialize>(path: P, val: &T) -> Result<()> {
+    if let Some(dir) = path.as_ref().parent() {
+        std::fs::create_dir_all(dir)?;
+    }
     let file = File::create(path)?;
     let writer = BufWriter::new(file);
 
@@ -0,0 +1,368 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{anyhow, Context, Result};
+use itertools::Itertools;
+use serde_json::{json, Value};
+
+use crate::{
+    forge::{parse_repo_url, Check, Forge, PrStatus, PullRequest},
+    git::{skip_in_dry_run, GitCommand},
+};
+
+/// Gitea or Forgejo, through their REST API (using `curl`).
+///
+/// Authenticates with the token in `GITEA_TOKEN` or `FORGEJO_TOKEN`, if set.
+#[derive(Debug)]
+pub struct Gitea {
+    /// e.g., `https://codeberg.org/api/v1`
+    api: String,
+    owner: String,
+    repo: String,
+    token: Option<String>,
+}
+
+/// Escapes `value` for a double-quoted string in a curl config file, which
+/// must stay on one line.
+fn curl_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Gitea {
+    /// Connects to the repository at `url`, a remote or web URL.
+    pub fn new(url: &str) -> Result<Self> {
+        let (base, owner, repo) = parse_repo_url(url)
+            .ok_or_else(|| anyhow!("cannot tell Gitea instance and repository from `{url}`"))?;
+        let token = ["GITEA_TOKEN", "FORGEJO_TOKEN"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|token| !token.is_empty()));
+
+        Ok(Self {
+            api: format!("{base}/api/v1"),
+            owner,
+            repo,
+            token,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/repos/{}/{}{path}", self.api, self.owner, self.repo)
+    }
+
+    /// Sends a `method` request to `path` (below the repository), returning
+    /// the response, or `null` if there is none.
+    fn api(&self, method: &str, path: &str, body: Option<Value>) -> Result<Value> {
+        let url = self.url(path);
+
+        let mut child = Command::new("curl")
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("failed to execute `curl`")?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(self.curl_config(method, &url, body.as_ref()).as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!("`{method} {url}` failed"));
+        }
+
+        let output = String::from_utf8(output.stdout)?;
+        let (response, code) = output.rsplit_once('\n').unwrap_or(("", &output));
+        let response: Value = if response.trim().is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(response)
+                .with_context(|| format!("parsing response of `{url}`"))?
+        };
+        if !code.starts_with('2') {
+            let message = response["message"].as_str().unwrap_or_default();
+            return Err(anyhow!("`{method} {url}` failed ({code}): {message}"));
+        }
+
+        Ok(response)
+    }
+
+    /// Returns the curl config for a `method` request to `url`, passed on
+    /// stdin to keep the token off the command line.
+    fn curl_config(&self, method: &str, url: &str, body: Option<&Value>) -> String {
+        let mut config = vec![
+            format!("url = {}", curl_quote(url)),
+            format!("request = {}", curl_quote(method)),
+            "silent".to_string(),
+            "show-error".to_string(),
+            format!("write-out = {}", curl_quote("\n%{http_code}")),
+            format!("header = {}", curl_quote("Accept: application/json")),
+        ];
+        if let Some(token) = &self.token {
+            let header = format!("Authorization: token {token}");
+            config.push(format!("header = {}", curl_quote(&header)));
+        }
+        if let Some(body) = body {
+            config.push(format!(
+                "header = {}",
+                curl_quote("Content-Type: application/json")
+            ));
+            config.push(format!("data-binary = {}", curl_quote(&body.to_string())));
+        }
+
+        config.join("\n")
+    }
+
+    /// Like [`Gitea::api()`] for requests that change something, which are
+    /// only printed in dry-run mode.
+    fn api_change(&self, method: &str, path: &str, body: Value) -> Result<Value> {
+        let url = self.url(path);
+        if skip_in_dry_run(
+            "curl",
+            ["--request", method, &url, "--data", &body.to_string()],
+        ) {
+            return Ok(Value::Null);
+        }
+
+        self.api(method, path, Some(body))
+    }
+}
+
+fn to_pull_request(pr: &Value) -> Result<PullRequest> {
+    let state = if pr["merged"].as_bool() == Some(true) {
+        "MERGED"
+    } else if pr["state"] == "open" {
+        "OPEN"
+    } else {
+        "CLOSED"
+    };
+
+    Ok(PullRequest {
+        number: pr["number"]
+            .as_u64()
+            .ok_or_else(|| anyhow!("PR without number"))? as u32,
+        head_ref_name: pr["head"]["ref"].as_str().unwrap_or_default().to_string(),
+        base_ref_name: pr["base"]["ref"].as_str().unwrap_or_default().to_string(),
+        state: state.to_string(),
+        url: pr["html_url"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+/// Returns title and description for a PR of `branch`, like `gh pr create --fill`.
+fn title_and_body(branch: &str, base: &str) -> Result<(String, String)> {
+    let output = GitCommand::new()
+        .args([
+            "log",
+            "--reverse",
+            "--format=%s",
+            &format!("{base}..{branch}"),
+        ])
+        .output()
+        .context("failed to execute git")?;
+    let subjects = String::from_utf8(output.stdout)?
+        .lines()
+        .map(str::to_string)
+        .collect_vec();
+
+    if let [subject] = &subjects[..] {
+        let output = GitCommand::new()
+            .args(["log", "-1", "--format=%b", branch])
+            .output()
+            .context("failed to execute git")?;
+        let body = String::from_utf8(output.stdout)?.trim().to_string();
+        return Ok((subject.clone(), body));
+    }
+
+    let body = subjects
+        .iter()
+        .map(|subject| format!("- {subject}"))
+        .join("\n");
+    Ok((branch.to_string(), body))
+}
+
+impl Forge for Gitea {
+    fn name(&self) -> &'static str {
+        "Gitea"
+    }
+
+    fn pr_comment(&self, number: u32, body: &str) -> Result<()> {
+        self.api_change(
+            "POST",
+            &format!("/issues/{number}/comments"),
+            json!({ "body": body }),
+        )?;
+        Ok(())
+    }
+
+    fn list_prs(&self) -> Result<Vec<PullRequest>> {
+        let mut prs = Vec::new();
+        for page in 1.. {
+            let response = self.api(
+                "GET",
+                &format!("/pulls?state=open&limit=50&page={page}"),
+                None,
+            )?;
+            let Some(batch) = response.as_array().filter(|batch| !batch.is_empty()) else {
+                break;
+            };
+            for pr in batch {
+                prs.push(to_pull_request(pr)?);
+            }
+        }
+
+        Ok(prs)
+    }
+
+    fn create_pr(
+        &self,
+        branch: &str,
+        head: &str,
+        base: &str,
+        draft: bool,
+    ) -> Result<(u32, String)> {
+        let (mut title, body) = title_and_body(branch, base)?;
+        // Gitea marks work in progress by title
+        if draft {
+            title = format!("WIP: {title}");
+        }
+
+        let response = self.api_change(
+            "POST",
+            "/pulls",
+            json!({ "head": head, "base": base, "title": title, "body": body }),
+        )?;
+        if response.is_null() {
+            return Ok((0, format!("(new PR for `{branch}`)")));
+        }
+        let pr = to_pull_request(&response)?;
+
+        Ok((pr.number, pr.url))
+    }
+
+    fn pr_view(&self, number: u32) -> Result<PullRequest> {
+        to_pull_request(&self.api("GET", &format!("/pulls/{number}"), None)?)
+            .with_context(|| format!("looking up PR #{number}"))
+    }
+
+    fn pr_status(&self, number: u32) -> Result<PrStatus> {
+        let pr = self.api("GET", &format!("/pulls/{number}"), None)?;
+        let state = to_pull_request(&pr)?.state;
+
+        let reviews = self.api("GET", &format!("/pulls/{number}/reviews"), None)?;
+        let reviews = reviews
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|review| review["dismissed"].as_bool() != Some(true))
+            .filter_map(|review| review["state"].as_str())
+            .collect_vec();
+        let review_decision = if reviews.contains(&"REQUEST_CHANGES") {
+            Some("CHANGES_REQUESTED".to_string())
+        } else if reviews.contains(&"APPROVED") {
+            Some("APPROVED".to_string())
+        } else {
+            None
+        };
+
+        let mut checks = Vec::new();
+        if let Some(sha) = pr["head"]["sha"].as_str() {
+            let status = self.api("GET", &format!("/commits/{sha}/status"), None)?;
+            for check in status["statuses"].as_array().into_iter().flatten() {
+                checks.push(Check {
+                    status: None,
+                    conclusion: None,
+                    state: check["status"].as_str().map(str::to_uppercase),
+                });
+            }
+        }
+
+        Ok(PrStatus {
+            state,
+            review_decision,
+            status_check_rollup: checks,
+        })
+    }
+
+    fn pr_body(&self, number: u32) -> Result<String> {
+        let pr = self.api("GET", &format!("/pulls/{number}"), None)?;
+        Ok(pr["body"].as_str().unwrap_or_default().to_string())
+    }
+
+    fn set_pr_body(&self, number: u32, body: &str) -> Result<()> {
+        self.api_change(
+            "PATCH",
+            &format!("/pulls/{number}"),
+            json!({ "body": body }),
+        )?;
+        Ok(())
+    }
+
+    fn set_pr_base(&self, number: u32, base: &str) -> Result<()> {
+        self.api_change(
+            "PATCH",
+            &format!("/pulls/{number}"),
+            json!({ "base": base }),
+        )?;
+        Ok(())
+    }
+
+    fn merge_pr(&self, number: u32) -> Result<()> {
+        self.api_change(
+            "POST",
+            &format!("/pulls/{number}/merge"),
+            json!({ "Do": "merge" }),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curl_quote_escapes() {
+        assert_eq!(curl_quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(curl_quote("a\nb\r\tc"), r#""a\nb\r\tc""#);
+    }
+
+    #[test]
+    fn curl_config_has_one_option_per_line() {
+        let gitea = Gitea {
+            api: "https://codeberg.org/api/v1".to_string(),
+            owner: "owner".to_string(),
+            repo: "repo".to_string(),
+            token: Some("secret".to_string()),
+        };
+        let body = json!({ "body": "two\nlines" });
+        let config = gitea.curl_config("POST", &gitea.url("/pulls"), Some(&body));
+
+        assert_eq!(
+            config.lines().collect_vec(),
+            [
+                r#"url = "https://codeberg.org/api/v1/repos/owner/repo/pulls""#,
+                r#"request = "POST""#,
+                "silent",
+                "show-error",
+                r#"write-out = "\n%{http_code}""#,
+                r#"header = "Accept: application/json""#,
+                r#"header = "Authorization: token secret""#,
+                r#"header = "Content-Type: application/json""#,
+                r#"data-binary = "{\"body\":\"two\\nlines\"}""#,
+            ]
+        );
+    }
+}
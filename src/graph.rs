@@ -1,24 +1,39 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
 use anyhow::{anyhow, Result};
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
+use itertools::Itertools;
 use petgraph::{
     acyclic::Acyclic,
+    algo::{astar, toposort},
+    data::Build,
     graph::{DiGraph, NodeIndex},
+    visit::{Dfs, DfsPostOrder, EdgeRef, IntoEdgeReferences, Reversed},
     Direction::{self, Incoming, Outgoing},
 };
 
-use crate::git::Repo;
+use crate::{
+    error::Error,
+    git::{Branch, Repo},
+    output::warning,
+    ui,
+};
 
 pub type BranchGraph = DiGraph<String, ()>;
 
+/// The dependencies between the branches of a [`Repo`], with edges pointing
+/// from a branch to the branches it depends on.
 #[derive(Debug)]
 pub struct GraphRepo {
     branch_map: IndexMap<String, NodeIndex>,
+    /// remote-tracking branches that local branches depend on
+    remotes: IndexSet<String>,
     pub graph: Acyclic<BranchGraph>,
 }
 
 impl GraphRepo {
     pub fn new(repo: &Repo) -> Result<Self> {
-        let branches = repo.branches()?;
+        let mut branches = repo.branches()?;
 
         let mut graph = BranchGraph::new();
 
@@ -29,23 +44,57 @@ impl GraphRepo {
             branch_map.insert(branch.name().clone(), index);
         }
 
+        let mut remotes = IndexSet::new();
         for branch in &branches {
-            let branch_index = branch_map.get(branch.name()).unwrap();
+            let branch_index = branch_map[branch.name()];
             for dep in branch.deps() {
                 if let Some(dep_index) = branch_map.get(&dep) {
-                    graph.add_edge(*branch_index, *dep_index, ());
+                    graph.add_edge(branch_index, *dep_index, ());
+                } else if repo.is_remote_branch(&dep) {
+                    let dep_index = graph.add_node(dep.clone());
+                    branch_map.insert(dep.clone(), dep_index);
+                    remotes.insert(dep);
+                    graph.add_edge(branch_index, dep_index, ());
                 } else {
-                    println!(
-                        "warning: branch `{}` depends on non-existing branch `{dep}`",
+                    warning!(
+                        "branch `{}` depends on non-existing branch `{dep}`",
                         branch.name()
                     );
                 }
             }
         }
 
-        let acyclic = Acyclic::try_from_graph(graph).unwrap();
+        while let Err(cycle) = toposort(&graph, None) {
+            let path = find_cycle(&graph, cycle.node_id());
+            let path_str = path.iter().map(|nx| &graph[*nx]).join(" → ");
+            let edges = path.iter().copied().tuple_windows::<(_, _)>().collect_vec();
+            let choices = edges
+                .iter()
+                .map(|(branch, dep)| {
+                    format!("drop dependency `{}` of `{}`", graph[*dep], graph[*branch])
+                })
+                .collect_vec();
+            let choice = ui::pick_one(&format!("dependency cycle detected: {path_str}"), &choices)?;
+
+            let (branch_index, dep_index) = edges[choice];
+            if let Some(edge) = graph.find_edge(branch_index, dep_index) {
+                graph.remove_edge(edge);
+            }
+
+            let dep = &graph[dep_index];
+            let branch = branches
+                .iter_mut()
+                .find(|branch| branch.name() == &graph[branch_index])
+                .ok_or_else(|| Error::BranchNotFound(graph[branch_index].clone()))?;
+            branch.state.deps.shift_remove(dep);
+            branch.save_state()?;
+        }
+
+        let acyclic =
+            Acyclic::try_from_graph(graph).map_err(|_| anyhow!("dependency graph has a cycle"))?;
         Ok(Self {
             branch_map,
+            remotes,
             graph: acyclic,
         })
     }
@@ -54,7 +103,22 @@ impl GraphRepo {
         let branch = branch.as_ref();
         self.branch_map
             .get(branch)
-            .ok_or(anyhow!("branch `{branch}` not found"))
+            .ok_or_else(|| Error::BranchNotFound(branch.to_string()).into())
+    }
+
+    /// Returns the remote-tracking branches that branches depend on.
+    pub fn remotes(&self) -> &IndexSet<String> {
+        &self.remotes
+    }
+
+    /// Adds remote-tracking branch `name` (which must exist), so that it
+    /// can become a dependency.
+    pub fn add_remote(&mut self, name: &str) {
+        if !self.branch_map.contains_key(name) {
+            let index = self.graph.add_node(name.to_string());
+            self.branch_map.insert(name.to_string(), index);
+            self.remotes.insert(name.to_string());
+        }
     }
 
     pub fn try_add_dep<T: AsRef<str>, S: AsRef<str>>(&mut self, branch: T, dep: S) -> Result<()> {
@@ -63,8 +127,9 @@ impl GraphRepo {
 
         self.graph
             .try_add_edge(*self.branch_id(branch)?, *self.branch_id(dep)?, ())
-            .map_err(|_| {
-                anyhow!("adding `{dep}` as dependency of `{branch}` would create a cycle")
+            .map_err(|_| Error::CycleDetected {
+                branch: branch.to_string(),
+                dep: dep.to_string(),
             })?;
 
         Ok(())
@@ -88,14 +153,212 @@ impl GraphRepo {
         self.get_neighbors(branch, Incoming)
     }
 
+    /// Returns all branches that (transitively) depend on `branch`, excluding `branch` itself.
+    ///
+    /// The result is in dependency order, so every branch comes after its dependencies.
+    pub fn get_descendants<T: AsRef<str>>(&self, branch: T) -> Result<Vec<String>> {
+        let start = *self.branch_id(branch)?;
+        let graph = Reversed(&*self.graph);
+        let mut dfs = Dfs::new(graph, start);
+        let mut descendants = HashSet::new();
+        while let Some(nx) = dfs.next(graph) {
+            if nx != start {
+                descendants.insert(nx);
+            }
+        }
+
+        Ok(self
+            .graph
+            .nodes_iter()
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+            .filter(|nx| descendants.contains(nx))
+            .map(|nx| self.graph[nx].clone())
+            .collect())
+    }
+
+    /// Returns all branches stacked on `default_branch` or on
+    /// remote-tracking branches, in dependency order.
+    pub fn stacked_branches<T: AsRef<str>>(&self, default_branch: T) -> Result<Vec<String>> {
+        let mut branches = self.get_descendants(default_branch)?;
+        for remote in &self.remotes {
+            for name in self.get_descendants(remote)? {
+                if !branches.contains(&name) {
+                    branches.push(name);
+                }
+            }
+        }
+
+        Ok(branches)
+    }
+
+    /// Returns the branches on the way from `from` to `to`, following the
+    /// dependency edges in either direction.
+    pub fn path<T: AsRef<str>, S: AsRef<str>>(
+        &self,
+        from: T,
+        to: S,
+    ) -> Result<Option<Vec<String>>> {
+        let from = *self.branch_id(from)?;
+        let to = *self.branch_id(to)?;
+
+        let path = astar(Reversed(&*self.graph), from, |nx| nx == to, |_| 1, |_| 0)
+            .or_else(|| astar(&*self.graph, from, |nx| nx == to, |_| 1, |_| 0))
+            .map(|(_, path)| path.iter().map(|nx| self.graph[*nx].clone()).collect());
+
+        Ok(path)
+    }
+
+    /// Returns the stack of `branch`: its dependencies (transitively), the branch
+    /// itself and its descendants, in dependency order.
+    ///
+    /// Remote-tracking branches it depends on are left out.
+    pub fn get_stack<T: AsRef<str>>(&self, branch: T) -> Result<Vec<String>> {
+        let branch = branch.as_ref();
+        let mut stack = Vec::new();
+        let mut dfs = DfsPostOrder::new(&*self.graph, *self.branch_id(branch)?);
+        while let Some(nx) = dfs.next(&*self.graph) {
+            if !self.remotes.contains(&self.graph[nx]) {
+                stack.push(self.graph[nx].clone());
+            }
+        }
+        stack.extend(self.get_descendants(branch)?);
+
+        Ok(stack)
+    }
+
+    /// Returns the root of the stack `branch` is in, and how deep `branch` sits in it.
+    ///
+    /// Follows the first dependency of each branch down to `trunk`, which is depth 0.
+    pub fn stack_position<T: AsRef<str>, S: AsRef<str>>(
+        &self,
+        branch: T,
+        trunk: S,
+    ) -> Option<(String, usize)> {
+        let trunk = *self.branch_id(trunk).ok()?;
+        let mut current = *self.branch_id(branch).ok()?;
+        let mut depth = 0;
+        let mut root = None;
+        while current != trunk {
+            root = Some(current);
+            // edges get added in dependency order, but are iterated newest first
+            current = self
+                .graph
+                .edges_directed(current, Outgoing)
+                .min_by_key(|edge| edge.id())?
+                .target();
+            depth += 1;
+        }
+
+        root.map(|root| (self.graph[root].clone(), depth))
+    }
+
+    /// Renders the graph as Mermaid flowchart, with edges pointing from each
+    /// dependency to its dependents.
+    pub fn to_mermaid(&self) -> String {
+        let mut out = String::from("```mermaid\ngraph TD\n");
+        for nx in self.graph.nodes_iter().collect_vec().into_iter().rev() {
+            let name = self.graph[nx].replace('"', "#quot;");
+            out.push_str(&format!("    n{}[\"{name}\"]\n", nx.index()));
+        }
+        for edge in self.graph.edge_references() {
+            out.push_str(&format!(
+                "    n{} --> n{}\n",
+                edge.target().index(),
+                edge.source().index()
+            ));
+        }
+        out.push_str("```\n");
+
+        out
+    }
+
     pub fn reversed(&self) -> Self {
         let branch_map = self.branch_map.clone();
+        let remotes = self.remotes.clone();
 
         let mut graph = self.graph.clone().into_inner();
         graph.reverse();
 
-        let graph = Acyclic::try_from_graph(graph).unwrap();
+        let graph =
+            Acyclic::try_from_graph(graph).expect("reversing an acyclic graph keeps it acyclic");
 
-        Self { branch_map, graph }
+        Self {
+            branch_map,
+            remotes,
+            graph,
+        }
     }
 }
+
+/// Returns a dependency cycle among `branches` (starting and ending with the
+/// same branch), if there is one.
+///
+/// Unlike [`GraphRepo::new()`], this doesn't ask to resolve it.
+pub fn dependency_cycle(branches: &[Branch]) -> Option<Vec<String>> {
+    dependency_cycle_in(
+        branches
+            .iter()
+            .map(|branch| (branch.name().clone(), branch.deps())),
+    )
+}
+
+/// Like [`dependency_cycle()`], for branches given by name and dependencies.
+pub fn dependency_cycle_in(
+    branches: impl IntoIterator<Item = (String, Vec<String>)>,
+) -> Option<Vec<String>> {
+    let branches = branches.into_iter().collect_vec();
+    let mut graph = BranchGraph::new();
+    let mut branch_map = HashMap::new();
+    for (name, _) in &branches {
+        branch_map.insert(name.clone(), graph.add_node(name.clone()));
+    }
+    for (name, deps) in &branches {
+        for dep in deps {
+            if let Some(dep_index) = branch_map.get(dep) {
+                graph.add_edge(branch_map[name], *dep_index, ());
+            }
+        }
+    }
+
+    let cycle = toposort(&graph, None).err()?;
+    Some(
+        find_cycle(&graph, cycle.node_id())
+            .into_iter()
+            .map(|nx| graph[nx].clone())
+            .collect(),
+    )
+}
+
+/// Returns a cycle through `start` as list of nodes, starting and ending with `start`.
+fn find_cycle(graph: &BranchGraph, start: NodeIndex) -> Vec<NodeIndex> {
+    let mut predecessors = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(nx) = queue.pop_front() {
+        for next in graph.neighbors_directed(nx, Outgoing) {
+            if predecessors.contains_key(&next) {
+                continue;
+            }
+            predecessors.insert(next, nx);
+            if next == start {
+                queue.clear();
+                break;
+            }
+            queue.push_back(next);
+        }
+    }
+
+    let mut path = vec![start];
+    let mut nx = start;
+    while let Some(&prev) = predecessors.get(&nx) {
+        path.push(prev);
+        if prev == start {
+            break;
+        }
+        nx = prev;
+    }
+    path.reverse();
+
+    path
+}
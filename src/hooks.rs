@@ -0,0 +1,117 @@
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
+
+use crate::{
+    git::{skip_in_dry_run, Repo, TrueOr},
+    output::{info, warning},
+};
+
+/// The points at which giddy runs user scripts from `.git/giddy/.hooks/`.
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    /// before rebasing (or merging into) a branch; a failing hook stops the update
+    PreUpdate,
+    /// after a branch was updated
+    PostUpdate,
+    /// after `giddy new` created a branch
+    PostNew,
+    /// before landing a branch; a failing hook stops landing it
+    PreLand,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Hook::PreUpdate => "pre-update",
+            Hook::PostUpdate => "post-update",
+            Hook::PostNew => "post-new",
+            Hook::PreLand => "pre-land",
+        }
+    }
+
+    fn is_pre(self) -> bool {
+        matches!(self, Hook::PreUpdate | Hook::PreLand)
+    }
+}
+
+/// What a hook gets told about the branch it runs for.
+///
+/// Passed as environment variables `GIDDY_BRANCH`, `GIDDY_BASE`,
+/// `GIDDY_OLD_HEAD` and `GIDDY_NEW_HEAD` (unset ones are left out); the
+/// branch name is also the first argument.
+#[derive(Debug, Default)]
+pub struct Env<'a> {
+    pub branch: &'a str,
+    pub base: Option<&'a str>,
+    pub old_head: Option<&'a str>,
+    pub new_head: Option<&'a str>,
+}
+
+fn path(repo: &Repo, hook: Hook) -> Utf8PathBuf {
+    repo.giddy_dir().join(".hooks").join(hook.name())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Utf8PathBuf) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    path.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Utf8PathBuf) -> bool {
+    path.is_file()
+}
+
+/// Runs `hook`, if it exists.
+///
+/// A failing `pre-*` hook is an error, failing `post-*` hooks only get a
+/// warning, as what they ran after is done already.
+pub fn run(repo: &Repo, hook: Hook, env: &Env) -> Result<()> {
+    let path = path(repo, hook);
+    if !path.exists() {
+        return Ok(());
+    }
+    if !is_executable(&path) {
+        warning!("hook `{path}` is not executable, ignoring it");
+        return Ok(());
+    }
+    if skip_in_dry_run(path.as_str(), [env.branch]) {
+        return Ok(());
+    }
+
+    info!("giddy: running {} hook for `{}`", hook.name(), env.branch);
+    let mut command = Command::new(&path);
+    command.arg(env.branch).env("GIDDY_BRANCH", env.branch);
+    for (var, value) in [
+        ("GIDDY_BASE", env.base),
+        ("GIDDY_OLD_HEAD", env.old_head),
+        ("GIDDY_NEW_HEAD", env.new_head),
+    ] {
+        if let Some(value) = value {
+            command.env(var, value);
+        }
+    }
+
+    let success = command
+        .status()
+        .with_context(|| format!("running hook `{path}`"))?
+        .success();
+    // the hook might have moved branches
+    repo.refs_changed();
+    if hook.is_pre() {
+        success.true_or(anyhow!(
+            "{} hook failed for branch `{}`",
+            hook.name(),
+            env.branch
+        ))
+    } else {
+        if !success {
+            warning!("{} hook failed for branch `{}`", hook.name(), env.branch);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
+use indexmap::IndexMap;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    git::{is_dry_run, read_from_file, write_to_file, Repo, TrueOr},
+    output::info,
+    plan::Snapshot,
+};
+
+/// Older entries get dropped.
+const MAX_ENTRIES: usize = 100;
+
+/// Subcommands that can change branches or their state.
+const JOURNALED: &[&str] = &[
+    "absorb", "add", "amend", "base", "bundle", "del", "delete", "doctor", "gc", "import", "init",
+    "land", "new", "pr", "prune", "rename", "reorder", "share", "split", "submit", "sync", "track",
+    "update",
+];
+
+/// One recorded operation.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// the giddy command line
+    pub command: String,
+    /// seconds since the epoch
+    pub time: u64,
+    /// branches the operation changed, as they were before; `None` for
+    /// branches it created
+    pub branches: IndexMap<String, Option<Snapshot>>,
+}
+
+/// Log of operations, so they can be undone.
+///
+/// Lives in `.git/giddy/.journal`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    entries: Vec<Entry>,
+}
+
+impl Journal {
+    fn path(repo: &Repo) -> Utf8PathBuf {
+        repo.git_dir().join("giddy").join(".journal")
+    }
+
+    pub fn load(repo: &Repo) -> Result<Self> {
+        let path = Self::path(repo);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        read_from_file(&path).context("reading operation journal")
+    }
+
+    pub fn save(&self, repo: &Repo) -> Result<()> {
+        if is_dry_run() {
+            return Ok(());
+        }
+        write_to_file(Self::path(repo), self).context("writing operation journal")
+    }
+
+    pub fn pop(&mut self) -> Option<Entry> {
+        self.entries.pop()
+    }
+}
+
+/// Returns whether running subcommand `name` gets recorded.
+pub fn is_journaled(name: &str) -> bool {
+    JOURNALED.contains(&name)
+}
+
+/// Returns heads and states of all branches.
+pub fn snapshot(repo: &Repo) -> Result<IndexMap<String, Snapshot>> {
+    let mut branches = IndexMap::new();
+    for line in repo.cmd_output_vec([
+        "for-each-ref",
+        "--format=%(refname:lstrip=2) %(objectname)",
+        "refs/heads",
+    ])? {
+        let Some((name, head)) = line.split_once(' ') else {
+            continue;
+        };
+        branches.insert(
+            name.to_string(),
+            Snapshot {
+                head: head.to_string(),
+                state: repo.state_of(name),
+            },
+        );
+    }
+
+    Ok(branches)
+}
+
+/// Records `command` in the journal, if it changed any branch since `before`.
+pub fn record(repo: &Repo, command: String, before: IndexMap<String, Snapshot>) -> Result<()> {
+    let after = snapshot(repo)?;
+
+    let mut branches = IndexMap::new();
+    for (name, snapshot) in &before {
+        let unchanged = after.get(name).is_some_and(|current| {
+            current.head == snapshot.head && current.state == snapshot.state
+        });
+        if !unchanged {
+            branches.insert(name.clone(), Some(snapshot.clone()));
+        }
+    }
+    for name in after.keys() {
+        if !before.contains_key(name) {
+            branches.insert(name.clone(), None);
+        }
+    }
+    if branches.is_empty() {
+        return Ok(());
+    }
+
+    let mut journal = Journal::load(repo)?;
+    if journal.entries.len() >= MAX_ENTRIES {
+        journal.entries.remove(0);
+    }
+    journal.entries.push(Entry {
+        command,
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs()),
+        branches,
+    });
+    journal.save(repo)
+}
+
+/// Puts the branches changed by `entry` back the way they were.
+pub fn restore(repo: &Repo, entry: &Entry) -> Result<()> {
+    for (name, snapshot) in &entry.branches {
+        let Some(snapshot) = snapshot else {
+            continue;
+        };
+
+        match repo.branch_head(name) {
+            Ok(head) if head == snapshot.head => {}
+            Ok(_) => repo.reset_branch(name, &snapshot.head)?,
+            Err(_) => repo
+                .cmd_check(["branch", name, &snapshot.head])?
+                .true_or(anyhow!("restoring branch `{name}` failed"))?,
+        }
+        repo.set_state_of(name, snapshot.state.as_ref())?;
+        info!("giddy: restored branch `{name}`");
+    }
+
+    // branches the operation created
+    let created = entry
+        .branches
+        .iter()
+        .filter(|(_, snapshot)| snapshot.is_none())
+        .map(|(name, _)| name)
+        .collect_vec();
+
+    // e.g., after a rename, go back to the old name instead of the default branch
+    let current = repo.branch_current()?.name().clone();
+    if created.contains(&&current) {
+        if let Some((restored, _)) = entry
+            .branches
+            .iter()
+            .find(|(_, snapshot)| snapshot.is_some())
+        {
+            repo.switch(restored)?;
+        }
+    }
+
+    for name in created {
+        if repo.branch_head(name).is_ok() {
+            repo.delete_branch(name)?;
+        }
+        repo.set_state_of(name, None)?;
+        info!("giddy: removed branch `{name}`");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Stacked branches on top of git: the library behind the `gd` command.
+//!
+//! [`git::Repo`] is the entry point. It gives access to the repository's
+//! branches ([`git::Branch`]), the dependencies giddy recorded for them
+//! ([`git::BranchState`]), and the graph those dependencies form
+//! ([`graph::GraphRepo`]):
+//!
+//! ```no_run
+//! use giddy::git::{Branch, Repo};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let repo = Repo::open()?;
+//! let graph = repo.graph()?;
+//! for name in graph.stacked_branches(repo.default_branch_name())? {
+//!     let branch = Branch::new(&name, &repo)?;
+//!     println!("{name} depends on {:?}", branch.deps());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Failures that are worth telling apart carry an [`Error`].
+//!
+//! Like the command, the library runs `git` (and the forge's tools) in the
+//! current directory and prints what it is doing; see [`output`] to silence
+//! that.
+
+pub mod config;
+mod error;
+pub mod forge;
+pub mod git;
+pub mod graph;
+pub mod output;
+
+pub use error::Error;
+
+// used by the `gd` command, not meant to be stable
+#[doc(hidden)]
+pub mod absorb;
+mod backend;
+mod cache;
+#[doc(hidden)]
+pub mod gerrit;
+mod gitea;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod journal;
+#[doc(hidden)]
+pub mod machete;
+#[doc(hidden)]
+pub mod naming;
+#[doc(hidden)]
+pub mod navigation;
+#[doc(hidden)]
+pub mod pager;
+#[doc(hidden)]
+pub mod parallel;
+#[doc(hidden)]
+pub mod plan;
+#[doc(hidden)]
+pub mod ui;
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Result};
+
+use crate::forge::parse_pr_ref;
+
+/// A branch from a git-machete branch layout file.
+#[derive(Debug)]
+pub struct Entry {
+    pub name: String,
+    /// the branch it is indented under, `None` for roots
+    pub parent: Option<String>,
+    /// PR number, if the annotation mentions one (`PR #123`)
+    pub pr: Option<u32>,
+}
+
+/// Parses the branch layout file of git-machete (usually `.git/machete`).
+///
+/// Each line names a branch, optionally followed by an annotation. Branches
+/// are children of the closest less indented branch above them.
+pub fn parse(contents: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    // indentation and name of the current branch at each level
+    let mut parents: Vec<(usize, String)> = Vec::new();
+
+    for (lineno, line) in contents.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap().to_string();
+        let pr = words.find_map(parse_pr_ref);
+
+        while parents.last().is_some_and(|(level, _)| *level >= indent) {
+            parents.pop();
+        }
+        if entries.iter().any(|entry: &Entry| entry.name == name) {
+            return Err(anyhow!(
+                "line {}: branch `{name}` is listed twice",
+                lineno + 1
+            ));
+        }
+
+        entries.push(Entry {
+            name: name.clone(),
+            parent: parents.last().map(|(_, parent)| parent.clone()),
+            pr,
+        });
+        parents.push((indent, name));
+    }
+
+    Ok(entries)
+}
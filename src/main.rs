@@ -1,28 +1,238 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::IsTerminal,
+};
+
 use anyhow::{anyhow, Context, Result};
+use giddy::{
+    absorb, config, gerrit, git,
+    git::TrueOr,
+    graph, hooks, journal, machete, naming, navigation, output,
+    output::{info, warning},
+    pager, parallel, plan, ui,
+};
+use indexmap::{IndexMap, IndexSet};
 use itertools::Itertools;
+use tracing_subscriber::filter::LevelFilter;
 
 mod cli;
-mod git;
-mod graph;
+#[cfg(feature = "tui")]
+mod tui;
+
+/// Logs to stderr: the git commands giddy runs with `-v`, also their output
+/// with `-vv`.
+fn init_logging(verbosity: u8, json: bool) {
+    let level = match verbosity {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    };
+    let logger = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_target(false)
+        .without_time();
+    if json {
+        logger.json().init();
+    } else {
+        logger.init();
+    }
+}
+
+/// Commands that honor `--branch`.
+const TARGET_BRANCH_COMMANDS: &[&str] = &["add", "base", "check", "del", "show"];
+
+/// Returns the branch given with `--branch`, or the current one.
+fn target_branch<'a>(repo: &'a git::Repo, matches: &clap::ArgMatches) -> Result<git::Branch<'a>> {
+    match matches.get_one::<String>("target-branch") {
+        Some(name) => named_branch(repo, name),
+        None => repo.branch_current(),
+    }
+}
+
+/// Returns branch `name`, which may also be a PR reference.
+fn named_branch<'a>(repo: &'a git::Repo, name: &str) -> Result<git::Branch<'a>> {
+    let name = repo.resolve_branch(name)?;
+    if !repo.branch_names()?.contains(&name) {
+        return Err(giddy::Error::BranchNotFound(name).into());
+    }
+
+    git::Branch::new(&name, repo)
+}
 
 fn run() -> Result<i32> {
     clap_complete::env::CompleteEnv::with_factory(cli::clap).complete();
 
     let matches = cli::clap().get_matches();
 
+    if matches.get_flag("non-interactive") || ui::is_ci() || !std::io::stdin().is_terminal() {
+        ui::set_non_interactive();
+    }
+    if matches.get_flag("no-pager") {
+        pager::disable();
+    }
+    if let Some((name, sub_matches)) = matches.subcommand() {
+        if sub_matches.get_one::<String>("target-branch").is_some()
+            && !TARGET_BRANCH_COMMANDS.contains(&name)
+        {
+            return Err(anyhow!("`giddy {name}` does not support `--branch`"));
+        }
+    }
+    let porcelain = matches
+        .subcommand()
+        .and_then(|(_, matches)| matches.try_get_one::<bool>("porcelain").ok().flatten());
+    if porcelain == Some(&true) {
+        output::set_porcelain();
+        pager::disable();
+    }
+    if matches.get_flag("dry-run") {
+        git::set_dry_run();
+    }
+    init_logging(
+        matches.get_count("verbose"),
+        matches.get_one::<String>("log-format").map(String::as_str) == Some("json"),
+    );
+    output::set_quiet(matches.get_count("quiet"));
+
+    // record the branches before anything changes, so that it can be undone
+    let journal = matches
+        .subcommand_name()
+        .filter(|name| journal::is_journaled(name) && !git::is_dry_run())
+        .map(|_| journal::snapshot(&git::Repo::open()?))
+        .transpose()?;
+
+    let result = dispatch(&matches);
+
+    let recorded = journal.map_or(Ok(()), |before| {
+        let command = std::env::args().skip(1).join(" ");
+        journal::record(&git::Repo::open()?, command, before)
+    });
+    result?;
+    recorded?;
+
+    Ok(0)
+}
+
+fn dispatch(matches: &clap::ArgMatches) -> Result<()> {
     match matches.subcommand() {
+        Some(("absorb", matches)) => {
+            handle_absorb(matches)?;
+        }
         Some(("add", matches)) => {
             handle_add(matches)?;
         }
+        Some(("amend", matches)) => {
+            handle_amend(matches)?;
+        }
+        Some(("base", matches)) => {
+            handle_base(matches)?;
+        }
+        Some(("bundle", matches)) => {
+            handle_bundle(matches)?;
+        }
+        Some(("check", matches)) => {
+            handle_check(matches)?;
+        }
+        Some(("co", matches)) => {
+            handle_co(matches)?;
+        }
         Some(("del", matches)) => {
             handle_del(matches)?;
         }
+        Some(("delete", matches)) => {
+            handle_delete(matches)?;
+        }
+        Some(("diff", matches)) => {
+            handle_diff(matches)?;
+        }
+        Some(("doctor", matches)) => {
+            handle_doctor(matches)?;
+        }
+        Some(("export", matches)) => {
+            handle_export(matches)?;
+        }
+        Some(("gc", matches)) => {
+            handle_gc(matches)?;
+        }
+        Some(("import", matches)) => {
+            handle_import(matches)?;
+        }
+        Some(("init", matches)) => {
+            handle_init(matches)?;
+        }
+        Some(("land", matches)) => {
+            handle_land(matches)?;
+        }
+        Some(("log", matches)) => {
+            handle_log(matches)?;
+        }
         Some(("new", matches)) => {
             handle_new(matches)?;
         }
+        Some(("next", matches)) => {
+            handle_next(matches)?;
+        }
+        Some(("notes", matches)) => {
+            handle_notes(matches)?;
+        }
+        Some(("path", matches)) => {
+            handle_path(matches)?;
+        }
+        Some(("pr", matches)) => {
+            handle_pr(matches)?;
+        }
+        Some(("prev", matches)) => {
+            handle_prev(matches)?;
+        }
+        Some(("prompt", matches)) => {
+            handle_prompt(matches)?;
+        }
+        Some(("prune", matches)) => {
+            handle_prune(matches)?;
+        }
+        Some(("push", matches)) => {
+            handle_push(matches)?;
+        }
+        Some(("rename", matches)) => {
+            handle_rename(matches)?;
+        }
+        Some(("reorder", matches)) => {
+            handle_reorder(matches)?;
+        }
+        Some(("roots", matches)) => {
+            handle_roots(matches)?;
+        }
+        Some(("share", matches)) => {
+            handle_share(matches)?;
+        }
         Some(("show", matches)) => {
             handle_show(matches)?;
         }
+        Some(("split", matches)) => {
+            handle_split(matches)?;
+        }
+        Some(("status", matches)) => {
+            handle_status(matches)?;
+        }
+        Some(("submit", matches)) => {
+            handle_submit(matches)?;
+        }
+        Some(("suggest", matches)) => {
+            handle_suggest(matches)?;
+        }
+        Some(("sync", matches)) => {
+            handle_sync(matches)?;
+        }
+        Some(("track", matches)) => {
+            handle_track(matches)?;
+        }
+        Some(("tui", matches)) => {
+            handle_tui(matches)?;
+        }
+        Some(("undo", matches)) => {
+            handle_undo(matches)?;
+        }
         Some(("update", matches)) => {
             handle_update(matches)?;
         }
@@ -30,27 +240,216 @@ fn run() -> Result<i32> {
         None => {}
     };
 
-    Ok(0)
+    Ok(())
+}
+
+fn handle_absorb(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+
+    // the current branch and the ones below it, following the first dependency
+    let mut chain = vec![current_branch];
+    loop {
+        let branch = chain.last().unwrap();
+        match branch.deps().first() {
+            Some(dep) if dep != &default_branch && branch.deps().len() == 1 => {
+                chain.push(Branch::new(dep, &repo)?);
+            }
+            Some(_) => break,
+            None => {
+                return Err(
+                    anyhow::Error::from(giddy::Error::NotTracked(branch.name().clone()))
+                        .context("cannot absorb into it"),
+                )
+            }
+        }
+    }
+
+    let base_of = |branch: &Branch| -> Result<String> {
+        match &branch.state.base_commit {
+            Some(base_commit) => Ok(base_commit.clone()),
+            None => {
+                let dep = &branch.deps()[0];
+                repo.merge_base(branch.name(), dep)?.ok_or_else(|| {
+                    anyhow!("`{}` has no common history with `{dep}`", branch.name())
+                })
+            }
+        }
+    };
+    let bottom = base_of(chain.last().unwrap())?;
+    let candidates: HashSet<String> = repo
+        .cmd_output_vec(["rev-list", "--no-merges", "HEAD", &format!("^{bottom}")])?
+        .into_iter()
+        .collect();
+
+    if repo
+        .git()
+        .args(["diff", "--cached", "--quiet"])
+        .status()?
+        .success()
+    {
+        return Err(anyhow!("nothing staged to absorb"));
+    }
+
+    if git::is_dry_run() {
+        for target in absorb::targets(&repo, &candidates)? {
+            match target.commit {
+                Some(commit) => println!("giddy: would absorb {} into {commit}", target.hunk),
+                None => println!("giddy: would leave {} staged", target.hunk),
+            }
+        }
+        return Ok(());
+    }
+
+    let fixups = absorb::create_fixups(&repo, &candidates)?;
+    if fixups.is_empty() {
+        return Err(anyhow!(
+            "none of the staged changes can be attributed to a commit of this stack"
+        ));
+    }
+
+    // the lowest branch that got a fixup
+    let mut lowest = 0;
+    for (commit, hunks) in &fixups {
+        let owner = (0..chain.len())
+            .rev()
+            .find(|i| repo.is_ancestor(commit, chain[*i].name()).unwrap_or(false))
+            .unwrap_or_default();
+        info!(
+            "giddy: absorbing {hunks} hunk(s) into {} on `{}`",
+            &commit[..12],
+            chain[owner].name()
+        );
+        lowest = lowest.max(owner);
+    }
+    if !repo
+        .git()
+        .args(["diff", "--cached", "--quiet"])
+        .status()?
+        .success()
+    {
+        info!("giddy: some hunks could not be attributed, they stay staged");
+    }
+
+    let base = base_of(&chain[lowest])?;
+    let squashed = repo.cmd_check([
+        "-c",
+        "sequence.editor=true",
+        "rebase",
+        "--quiet",
+        "--interactive",
+        "--autosquash",
+        "--autostash",
+        "--update-refs",
+        &base,
+    ])?;
+    if !squashed {
+        repo.cmd_check(["rebase", "--abort"])?;
+        return Err(anyhow!(
+            "squashing the fixups failed, they are left on top of `{}` (squash them with `git rebase -i --autosquash --update-refs {base}`)",
+            chain[0].name()
+        ));
+    }
+
+    // `--update-refs` moved the branches in between along
+    for i in (0..lowest).rev() {
+        let mut branch = Branch::new(chain[i].name(), &repo)?;
+        branch.state.base_commit = Some(repo.branch_head(chain[i + 1].name())?);
+        branch.save_state()?;
+    }
+
+    let chain = chain
+        .iter()
+        .map(|branch| branch.name().clone())
+        .collect_vec();
+    let dependents = repo
+        .graph()?
+        .get_descendants(&chain[lowest])?
+        .into_iter()
+        .filter(|name| !chain.contains(name))
+        .collect_vec();
+
+    restack_branches(&repo, &dependents, &chain[0])
+}
+
+/// Updates `names` (in that order), stashing local changes meanwhile, then
+/// switches back to `current`.
+fn restack_branches(repo: &git::Repo, names: &[String], current: &str) -> Result<()> {
+    use git::Branch;
+
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    // restacking checks the branches out
+    let stashed = repo.stash_push()?;
+    let options = UpdateOptions::default();
+    let result = names.iter().try_for_each(|name| {
+        let mut branch = Branch::new(name, repo)?;
+        update_branch(repo, &mut branch, &options).map(|_| ())
+    });
+    if result.is_ok() && repo.branch_current()?.name() != current {
+        repo.switch(current)?;
+    }
+    if let Some(stash) = stashed {
+        if repo.rebase_in_progress() {
+            info!("giddy: your local changes are stashed as {stash}, restore them with `git stash pop` when done");
+        } else {
+            repo.stash_pop(&stash)?;
+        }
+    }
+
+    result
 }
 
 fn handle_add(matches: &clap::ArgMatches) -> Result<()> {
-    let deps: Vec<&String> = matches.get_many("dependency").unwrap().collect();
-    let repo = git::Repo::new();
-    let mut current_branch = repo.branch_current()?;
+    let repo = git::Repo::open()?;
+    let mut current_branch = target_branch(&repo, matches)?;
     let previous_deps = current_branch.state.deps.clone();
     let mut graph = repo.graph()?;
-    for dep in deps {
+
+    let deps: Vec<String> = match matches.get_many::<String>("dependency") {
+        Some(deps) => deps
+            .map(|dep| repo.resolve_branch(dep))
+            .collect::<Result<_>>()?,
+        None => {
+            let descendants = graph.get_descendants(current_branch.name())?;
+            let candidates = repo
+                .branch_names()?
+                .into_iter()
+                .filter(|name| name != current_branch.name())
+                .filter(|name| !previous_deps.contains(name) && !descendants.contains(name))
+                .collect_vec();
+            if candidates.is_empty() {
+                return Err(anyhow!("no branches available as dependency"));
+            }
+            ui::pick_many(
+                &format!("dependencies to add to `{}`", current_branch.name()),
+                &candidates,
+                &vec![false; candidates.len()],
+            )?
+        }
+    };
+
+    for dep in &deps {
         if previous_deps.contains(dep) {
-            println!(
+            info!(
                 "branch `{}` already depends on `{dep}`",
                 current_branch.name()
             );
             continue;
         }
-        println!(
+        info!(
             "adding dependency `{dep}` to branch `{}`",
             current_branch.name()
         );
+        if repo.is_remote_branch(dep) {
+            graph.add_remote(dep);
+        }
         graph.try_add_dep(current_branch.name(), dep)?;
         current_branch.state.deps.insert(dep.clone());
     }
@@ -59,132 +458,3411 @@ fn handle_add(matches: &clap::ArgMatches) -> Result<()> {
     Ok(())
 }
 
-fn handle_del(matches: &clap::ArgMatches) -> Result<()> {
-    let deps: Vec<&String> = matches.get_many("dependency").unwrap().collect();
-    let repo = git::Repo::new();
-    let mut current_branch = repo.branch_current()?;
-    for dep in deps {
-        println!(
-            "removing dependency `{dep}` from branch `{}`",
-            current_branch.name()
-        );
-        let did_remove = current_branch.state.deps.shift_remove(dep);
-        if !did_remove {
-            println!(
-                "warning: dependency `{dep}` was not a dependency of branch `{}`!",
-                current_branch.name()
-            );
+fn handle_base(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+    let mut current_branch = target_branch(&repo, matches)?;
+    let name = current_branch.name().clone();
+
+    let Some(("set", matches)) = matches.subcommand() else {
+        let base = current_branch
+            .state
+            .base
+            .as_ref()
+            .ok_or_else(|| anyhow!("branch `{name}` has no base"))?;
+        match &current_branch.state.base_commit {
+            Some(commit) => println!("{base} ({})", &commit[..commit.len().min(12)]),
+            None => println!("{base}"),
         }
+        return Ok(());
+    };
+
+    let base = repo.resolve_branch(matches.get_one::<String>("branch").unwrap())?;
+    (base != name).true_or(anyhow!("branch `{name}` cannot be its own base"))?;
+    repo.graph()?
+        .get_descendants(&name)?
+        .iter()
+        .all(|descendant| descendant != &base)
+        .true_or(anyhow!(
+            "`{base}` depends on `{name}`, it cannot be its base"
+        ))?;
+
+    // only the first dependency gets replaced, like `update` rebases onto it
+    let old = current_branch.deps().into_iter().next();
+    let mut deps = IndexSet::from([base.clone()]);
+    deps.extend(
+        current_branch
+            .state
+            .deps
+            .iter()
+            .filter(|dep| Some(*dep) != old.as_ref())
+            .cloned(),
+    );
+    current_branch.state.deps = deps;
+
+    // the commits after the fork point are the branch's own
+    let base_commit = match current_branch.fork_point(&base)? {
+        Some(commit) => Some(commit),
+        None => current_branch.merge_base(&base)?,
+    };
+    if base_commit.is_none() {
+        warning!("`{name}` and `{base}` have no common history");
     }
+    current_branch.state.base_commit = base_commit;
+    current_branch.state.base = Some(base.clone());
     current_branch.save_state()?;
 
+    info!("giddy: `{name}` is now based on `{base}`, run `giddy update {name}` to rebase it");
+
     Ok(())
 }
 
-fn handle_new(matches: &clap::ArgMatches) -> Result<()> {
-    let name = matches.get_one("name");
-    let repo = git::Repo::new();
+fn handle_amend(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
     let current_branch = repo.branch_current()?;
+    let name = current_branch.name();
 
-    let name = name.cloned().unwrap_or_else(|| {
-        let suffix = format!("{:x}", rand::random::<u64>());
-        format!("{}-{}", current_branch.name(), suffix)
-    });
+    let mut args = vec!["commit".to_string(), "--quiet".to_string()];
+    if matches.get_flag("all") {
+        args.push("--all".into());
+    }
 
-    println!("giddy: creating new branch `{name}`");
-    let mut new_branch = repo.branch_create(&name)?;
+    match matches.get_one::<String>("fixup") {
+        Some(commit) => {
+            let commit = repo.branch_head(format!("{commit}^{{commit}}"))?;
+            let own = match &current_branch.state.base_commit {
+                Some(base_commit) => {
+                    &commit != base_commit && repo.is_ancestor(base_commit, &commit)?
+                }
+                None => !current_branch
+                    .deps()
+                    .iter()
+                    .any(|dep| repo.is_ancestor(&commit, dep).unwrap_or(true)),
+            };
+            if !own || !repo.is_ancestor(&commit, name)? {
+                return Err(anyhow!(
+                    "commit {commit} is not one of `{name}`'s own commits (`giddy absorb` can fix up commits of lower branches)"
+                ));
+            }
 
-    println!(
-        "giddy: adding `{}` as dependency of `{name}`",
-        current_branch.name(),
-    );
-    new_branch.state.deps.insert(current_branch.name().clone());
-    new_branch
-        .save_state()
-        .with_context(|| anyhow!("saving state for branch `{}`", new_branch.name()))?;
+            args.push(format!("--fixup={commit}"));
+            repo.cmd_check(&args)?
+                .true_or(anyhow!("committing the fixup failed"))?;
+            repo.cmd_check([
+                "-c",
+                "sequence.editor=true",
+                "rebase",
+                "--quiet",
+                "--interactive",
+                "--autosquash",
+                "--autostash",
+                &format!("{commit}~"),
+            ])?
+            .true_or(anyhow!(
+                "squashing the fixup into {commit} failed, resolve the conflicts and run `git rebase --continue`"
+            ))?;
+        }
+        None => {
+            args.push("--amend".into());
+            match matches.get_one::<String>("message") {
+                Some(message) => args.extend(["--message".to_string(), message.clone()]),
+                None => args.push("--no-edit".into()),
+            }
+            repo.cmd_check(&args)?
+                .true_or(anyhow!("amending the commit failed"))?;
+        }
+    }
+    info!("giddy: amended `{name}`");
+
+    let dependents = repo.graph()?.get_descendants(name)?;
+    restack_branches(&repo, &dependents, name)
+}
+
+/// Temporary ref holding the branch states while creating or applying a bundle.
+const BUNDLE_STATE_REF: &str = "refs/giddy/bundle-state";
+/// Ref `giddy share` keeps the branch states in, on the remote as well.
+const SHARE_REF: &str = "refs/giddy/meta";
+
+fn handle_bundle(matches: &clap::ArgMatches) -> Result<()> {
+    use git::{Branch, BranchState};
+
+    let repo = git::Repo::open()?;
+
+    match matches.subcommand() {
+        Some(("create", matches)) => {
+            let file: &String = matches.get_one("file").unwrap();
+            let current_branch = repo.branch_current()?;
+            let default_branch = repo.default_branch_name();
+
+            let mut branch_names = repo.graph()?.get_stack(current_branch.name())?;
+            branch_names.retain(|name| name != &default_branch);
+
+            let mut states = IndexMap::new();
+            for name in &branch_names {
+                states.insert(name.clone(), Branch::new(name, &repo)?.state);
+            }
+            repo.write_ref_file(
+                BUNDLE_STATE_REF,
+                "state.json",
+                &serde_json::to_string_pretty(&states)?,
+                None,
+            )?;
+
+            let mut args = vec!["bundle".to_string(), "create".to_string(), file.clone()];
+            args.extend(branch_names.iter().cloned());
+            args.push(BUNDLE_STATE_REF.to_string());
+            args.push(format!("^{default_branch}"));
+            let created = repo.cmd_check(args)?;
+            repo.cmd_check(["update-ref", "-d", BUNDLE_STATE_REF])?;
+            created.true_or(anyhow!("creating bundle `{file}` failed"))?;
+
+            info!(
+                "giddy: bundled branches {} into `{file}`",
+                branch_names
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .join(", ")
+            );
+        }
+        Some(("apply", matches)) => {
+            let file: &String = matches.get_one("file").unwrap();
+            repo.git()
+                .args(["bundle", "verify", "--quiet", file])
+                .status()?
+                .success()
+                .true_or(anyhow!(
+                    "bundle `{file}` cannot be applied to this repository"
+                ))?;
+
+            // the state ref is only needed temporarily, so fetch it even in dry-run mode
+            repo.git()
+                .args([
+                    "fetch",
+                    "--quiet",
+                    file,
+                    &format!("+{BUNDLE_STATE_REF}:{BUNDLE_STATE_REF}"),
+                ])
+                .status()?
+                .success()
+                .true_or(anyhow!("reading giddy state from `{file}` failed"))?;
+            let states = repo.read_ref_file(BUNDLE_STATE_REF, "state.json");
+            repo.git()
+                .args(["update-ref", "-d", BUNDLE_STATE_REF])
+                .status()?;
+            let states: IndexMap<String, BranchState> = serde_json::from_str(&states?)
+                .with_context(|| format!("parsing the giddy state in `{file}`"))?;
+
+            let overwrite = matches.get_flag("overwrite");
+            let current_branch = repo.branch_current()?;
+            let existing = repo.branch_names()?;
+            let mut applied = IndexMap::new();
+            for (name, state) in states {
+                if &name == current_branch.name() {
+                    warning!("branch `{name}` is checked out, skipping");
+                    continue;
+                }
+                let exists = existing.contains(&name) || repo.state_of(&name).is_some();
+                if exists && !overwrite {
+                    warning!("branch `{name}` exists, skipping (use `--overwrite` to replace it)");
+                    continue;
+                }
+
+                let force = if overwrite { "+" } else { "" };
+                repo.cmd_check([
+                    "fetch",
+                    "--quiet",
+                    file,
+                    &format!("{force}refs/heads/{name}:refs/heads/{name}"),
+                ])?
+                .true_or(anyhow!("fetching branch `{name}` from `{file}` failed"))?;
+                applied.insert(name, state);
+            }
+
+            import_state_map(&repo, applied, &format!("`{file}`"), overwrite)?;
+        }
+        _ => unreachable!(),
+    }
 
     Ok(())
 }
 
-fn handle_show(matches: &clap::ArgMatches) -> Result<()> {
-    let _ = matches;
-    let repo = git::Repo::new();
+/// Checks the target branch's state against the repository, printing one
+/// `<check>\t<ok|fail|skip>\t<details>` line per check.
+fn handle_check(matches: &clap::ArgMatches) -> Result<()> {
+    use git::BranchState;
 
-    let current_branch = repo.branch_current()?;
-    let default_branch = repo.branch_default()?;
-    let base_branch = current_branch.state.base.as_ref();
+    ui::set_non_interactive();
+    let repo = git::Repo::open()?;
+    let default_branch = repo.default_branch_name();
+    let remote = match matches.get_one::<String>("remote") {
+        Some(remote) => remote.clone(),
+        None => repo.push_remote(&default_branch),
+    };
 
-    println!("git dir: {}", repo.git_dir());
-    println!(
-        "current branch: {} (parent: {}{})",
-        current_branch.name(),
-        base_branch.unwrap_or(&String::from("none")),
-        if current_branch.merged().is_ok_and(|merged| merged) {
-            " (merged)"
-        } else if current_branch.equal(default_branch.name())? {
-            " (equal)"
-        } else if current_branch.state.dirty {
-            " (dirty)"
+    // CI checkouts have no giddy state of their own
+    let states: IndexMap<String, BranchState> =
+        if matches.get_flag("shared") || !repo.giddy_dir().exists() {
+            let contents = fetch_shared_states(&repo, &remote)?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("parsing `{SHARE_REF}` of `{remote}`"))?
         } else {
-            ""
+            repo.branches()?
+                .into_iter()
+                .filter(|branch| branch.has_state())
+                .map(|branch| (branch.name().clone(), branch.state))
+                .collect()
+        };
+
+    // ... and often only remote-tracking branches
+    let head = |name: &str| {
+        [
+            format!("refs/heads/{name}"),
+            format!("refs/remotes/{name}"),
+            format!("refs/remotes/{remote}/{name}"),
+        ]
+        .iter()
+        .find_map(|rev| repo.branch_head(rev).ok())
+    };
+
+    let name = match matches.get_one::<String>("target-branch") {
+        Some(name) => name.clone(),
+        None => match repo.branch_current() {
+            Ok(branch) => branch.name().clone(),
+            Err(e) if matches!(e.downcast_ref(), Some(giddy::Error::DetachedHead)) => {
+                let checked_out = repo.branch_head("HEAD")?;
+                let candidates = states
+                    .keys()
+                    .filter(|name| head(name).as_ref() == Some(&checked_out))
+                    .collect_vec();
+                match candidates.as_slice() {
+                    [name] => name.to_string(),
+                    [] => {
+                        return Err(anyhow!(
+                            "HEAD is detached at {checked_out}, which is no tracked branch, use `--branch`"
+                        ))
+                    }
+                    _ => {
+                        return Err(anyhow!(
+                            "HEAD is detached at the head of {}, use `--branch`",
+                            candidates.iter().map(|name| format!("`{name}`")).join(", ")
+                        ))
+                    }
+                }
+            }
+            Err(e) => return Err(e),
+        },
+    };
+    let branch_head = head(&name).ok_or_else(|| giddy::Error::BranchNotFound(name.clone()))?;
+
+    let mut failed = Vec::new();
+    let mut report = |check: &str, result: Option<bool>, details: String| {
+        let status = match result {
+            Some(true) => "ok",
+            Some(false) => "fail",
+            None => "skip",
+        };
+        if result == Some(false) {
+            failed.push(check.to_string());
+        }
+        println!("{check}\t{status}\t{details}");
+    };
+
+    let Some(state) = states.get(&name) else {
+        report("tracked", Some(false), format!("`{name}` is not tracked"));
+        return Err(giddy::Error::CheckFailed(failed).into());
+    };
+    report("tracked", Some(true), String::new());
+
+    // like `Branch::deps()`, without needing a local branch
+    let deps_of = |name: &str, state: &BranchState| -> Vec<String> {
+        if !state.deps.is_empty() {
+            state.deps.iter().cloned().collect()
+        } else if name == default_branch || repo.is_remote_branch(name) {
+            Vec::new()
+        } else {
+            vec![default_branch.clone()]
         }
+    };
+    let deps = deps_of(&name, state);
+    let dep_heads = deps.iter().map(|dep| (dep, head(dep))).collect_vec();
+
+    let missing = dep_heads
+        .iter()
+        .filter(|(_, head)| head.is_none())
+        .map(|(dep, _)| format!("`{dep}`"))
+        .join(", ");
+    report(
+        "deps-exist",
+        Some(missing.is_empty()),
+        if missing.is_empty() {
+            String::new()
+        } else {
+            format!("missing: {missing}")
+        },
     );
 
-    println!("  needs update: {}", current_branch.needs_update()?);
-    if !current_branch.state.deps.is_empty() {
-        println!(
-            "          deps: {}",
-            current_branch.state.deps.iter().join(", ")
-        );
+    match &state.base {
+        // merging several deps doesn't keep track of a base
+        Some(_) if deps.len() > 1 => report("base-is-dep", None, "merges its dependencies".into()),
+        Some(base) => report(
+            "base-is-dep",
+            Some(deps.contains(base)),
+            format!("base `{base}`"),
+        ),
+        None => report("base-is-dep", None, "no base recorded".into()),
     }
 
-    println!("default branch: {}", default_branch.name());
+    match graph::dependency_cycle_in(
+        states
+            .iter()
+            .map(|(name, state)| (name.clone(), deps_of(name, state))),
+    ) {
+        Some(cycle) => report("no-cycle", Some(false), cycle.join(" → ")),
+        None => report("no-cycle", Some(true), String::new()),
+    }
 
-    if matches.get_flag("tree") {
-        let graph = repo.graph()?;
-        use ptree::graph::print_graph;
+    match &state.base_commit {
+        Some(base_commit) => report(
+            "base-ancestor",
+            Some(repo.is_ancestor(base_commit, &branch_head).unwrap_or(false)),
+            format!("base commit {base_commit}"),
+        ),
+        None => report("base-ancestor", None, "no base commit recorded".into()),
+    }
 
-        let graph = graph.reversed();
-        let branch_id = *graph.branch_id(default_branch.name())?;
-        let graph = graph.graph.into_inner();
+    if state.planned {
+        report("up-to-date", None, "planned".into());
+    } else {
+        // up to date means containing the current head of every dependency
+        let mut behind = Vec::new();
+        for (dep, dep_head) in &dep_heads {
+            let contained = match dep_head {
+                Some(dep_head) => repo.is_ancestor(dep_head, &branch_head)?,
+                None => false,
+            };
+            if !contained {
+                behind.push(format!("`{dep}`"));
+            }
+        }
+        if behind.is_empty() {
+            let deps = deps.iter().map(|dep| format!("`{dep}`")).join(", ");
+            report("up-to-date", Some(true), format!("on {deps}"));
+        } else {
+            report(
+                "up-to-date",
+                Some(false),
+                format!("behind {}", behind.join(", ")),
+            );
+        }
+    }
 
-        print_graph(&graph, branch_id)?;
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(giddy::Error::CheckFailed(failed).into())
+    }
+}
+
+fn handle_co(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+
+    let name = match matches.get_one::<String>("branch") {
+        Some(name) => repo.resolve_branch(name)?,
+        None => {
+            let default_branch = repo.default_branch_name();
+            let graph = repo.graph()?;
+            let mut names = vec![default_branch.clone()];
+            names.extend(graph.get_descendants(&default_branch)?);
+
+            let mut items = Vec::new();
+            for name in &names {
+                let branch = Branch::new(name, &repo)?;
+                let mut info = Vec::new();
+                if let Some((root, depth)) = graph.stack_position(name, &default_branch) {
+                    info.push(format!("stack: {root}, depth {depth}"));
+                }
+                if branch.state.planned {
+                    info.push("planned".into());
+                } else if name != &default_branch && branch.needs_update()? {
+                    info.push("needs update".into());
+                }
+                if let Some(pr) = branch.state.pr {
+                    info.push(format!("PR #{pr}"));
+                }
+
+                items.push(if info.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{name}  ({})", info.join(", "))
+                });
+            }
+
+            let current = names
+                .iter()
+                .position(|name| name == current_branch.name())
+                .unwrap_or_default();
+            names.swap_remove(ui::pick_fuzzy("switch to branch", &items, current)?)
+        }
+    };
+
+    if &name == current_branch.name() {
+        info!("giddy: already on `{name}`");
+        return Ok(());
+    }
+
+    repo.switch(&name)
+}
+
+fn handle_del(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+    let mut current_branch = target_branch(&repo, matches)?;
+
+    let deps: Vec<String> = match matches.get_many::<String>("dependency") {
+        Some(deps) => deps
+            .map(|dep| repo.resolve_branch(dep))
+            .collect::<Result<_>>()?,
+        None => {
+            let current_deps = current_branch.state.deps.iter().cloned().collect_vec();
+            if current_deps.is_empty() {
+                return Err(anyhow!(
+                    "branch `{}` has no dependencies",
+                    current_branch.name()
+                ));
+            }
+            let keep = ui::pick_many(
+                &format!("dependencies of `{}` to keep", current_branch.name()),
+                &current_deps,
+                &vec![true; current_deps.len()],
+            )?;
+            current_deps
+                .into_iter()
+                .filter(|dep| !keep.contains(dep))
+                .collect()
+        }
+    };
+
+    for dep in &deps {
+        info!(
+            "removing dependency `{dep}` from branch `{}`",
+            current_branch.name()
+        );
+        let did_remove = current_branch.state.deps.shift_remove(dep);
+        if !did_remove {
+            warning!(
+                "dependency `{dep}` was not a dependency of branch `{}`!",
+                current_branch.name()
+            );
+        }
+    }
+    current_branch.save_state()?;
+
+    if matches.get_flag("drop-commits") {
+        for dep in &deps {
+            current_branch.drop_commits_of(dep)?;
+        }
+        let current = [current_branch.name().clone()];
+        restack_stale_dependents(&repo, &current, &current, &UpdateOptions::default())?;
     }
 
     Ok(())
 }
 
-fn handle_update(matches: &clap::ArgMatches) -> Result<()> {
-    let recursive = matches.get_flag("recursive");
-    let repo = git::Repo::new();
-    let current_branch = repo.branch_current()?;
+fn handle_delete(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
 
-    if recursive {
-        use git::Branch;
-        use petgraph::visit::DfsPostOrder;
+    let repo = git::Repo::open()?;
+    let force = matches.get_flag("force");
+    let default_branch = repo.default_branch_name();
 
-        let graph = repo.graph()?;
+    let names: Vec<String> = matches
+        .get_many::<String>("branch")
+        .unwrap()
+        .map(|name| repo.resolve_branch(name))
+        .collect::<Result<_>>()?;
 
-        let mut dfs = DfsPostOrder::new(&graph.graph, *graph.branch_id(current_branch.name())?);
-        while let Some(nx) = dfs.next(&graph.graph) {
-            let branch_name = &graph.graph[nx];
-            let mut branch = Branch::new(branch_name, &repo)?;
-            branch.update()?
+    for name in &names {
+        if name == &default_branch {
+            return Err(anyhow!("refusing to delete the default branch `{name}`"));
         }
-    } else {
-        let mut current_branch = repo.branch_current()?;
-        current_branch.update()?;
+        repo.graph()?.branch_id(name)?;
+
+        let branch = Branch::new(name, &repo)?;
+        if !force
+            && branch.has_own_commits()?
+            && !branch.merged_into(&default_branch)?
+            && !ui::confirm(&format!(
+                "branch `{name}` has commits that are not merged into `{default_branch}`, delete anyway?"
+            ))?
+        {
+            continue;
+        }
+
+        repo.unlink_branch(name)?;
+        repo.delete_branch(name)?;
+        info!("giddy: deleted branch `{name}`");
     }
 
     Ok(())
 }
 
-fn main() {
+fn handle_diff(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+    let name = match matches.get_one::<String>("branch") {
+        Some(branch) => repo.resolve_branch(branch)?,
+        None => repo.branch_current()?.name().clone(),
+    };
+    let branch = git::Branch::new(&name, &repo)?;
+    let base = branch
+        .state
+        .base
+        .clone()
+        .or_else(|| branch.deps().into_iter().next())
+        .ok_or_else(|| anyhow!("branch `{name}` has no base to diff against"))?;
+
+    let mut diff = repo.git();
+    diff.arg("diff");
+    for flag in ["stat", "name-only"] {
+        if matches.get_flag(flag) {
+            diff.arg(format!("--{flag}"));
+        }
+    }
+    // the recorded base commit stays right when the base moved on since
+    match &branch.state.base_commit {
+        Some(base_commit) => diff.args([base_commit, &name]),
+        None => diff.arg(format!("{base}...{name}")),
+    };
+    diff.status()?
+        .success()
+        .true_or(anyhow!("`git diff` failed"))
+}
+
+fn handle_doctor(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let fix = matches.get_flag("fix");
+    let default_branch = repo.default_branch_name();
+    let mut found = 0;
+    let mut fixed = 0;
+
+    // before their states go, for reparenting their dependents like `gc`
+    let orphaned = orphaned_deps(&repo)?;
+    for name in orphaned.keys().sorted() {
+        found += 1;
+        println!("branch `{name}`: has a state file, but the branch doesn't exist");
+        if fix {
+            repo.set_state_of(name, None)?;
+            println!("  fixed: removed the state file");
+            fixed += 1;
+        }
+    }
+
+    let names = repo.branch_names()?;
+    let exists = |name: &str| names.iter().any(|n| n == name) || repo.is_remote_branch(name);
+    for mut branch in repo.branches()? {
+        let missing = branch
+            .state
+            .deps
+            .iter()
+            .filter(|dep| !exists(dep))
+            .cloned()
+            .collect_vec();
+        for dep in &missing {
+            found += 1;
+            println!(
+                "branch `{}`: depends on non-existing branch `{dep}`",
+                branch.name()
+            );
+        }
+        let base_missing = branch.state.base.as_ref().filter(|base| !exists(base));
+        if let Some(base) = base_missing {
+            found += 1;
+            println!(
+                "branch `{}`: base branch `{base}` doesn't exist",
+                branch.name()
+            );
+        }
+        let problems = missing.len() + usize::from(base_missing.is_some());
+
+        if fix && reparent_missing_deps(&repo, &mut branch, &orphaned, &exists)? {
+            println!(
+                "  fixed: depends on {} now, with base `{}`",
+                branch
+                    .state
+                    .deps
+                    .iter()
+                    .map(|dep| format!("`{dep}`"))
+                    .join(", "),
+                branch.state.base.as_deref().unwrap_or("none")
+            );
+            fixed += problems;
+        }
+    }
+
+    while let Some(cycle) = graph::dependency_cycle(&repo.branches()?) {
+        found += 1;
+        println!("dependency cycle: {}", cycle.join(" → "));
+        if !fix {
+            break;
+        }
+        let mut branch = Branch::new(&cycle[0], &repo)?;
+        branch.state.deps.shift_remove(&cycle[1]);
+        branch.save_state()?;
+        println!(
+            "  fixed: removed dependency `{}` of `{}`",
+            cycle[1], cycle[0]
+        );
+        fixed += 1;
+    }
+
+    for mut branch in repo.branches()? {
+        if branch.name() == &default_branch || branch.state.planned {
+            continue;
+        }
+        let deps = branch.deps();
+        let single_dep = (deps.len() == 1).then(|| &deps[0]);
+
+        if let Some(base_commit) = branch.state.base_commit.clone() {
+            if !repo.is_ancestor(&base_commit, &branch.head()?)? {
+                found += 1;
+                println!(
+                    "branch `{}`: recorded base commit {base_commit} is not part of the branch",
+                    branch.name()
+                );
+                if let (true, Some(dep)) = (fix, single_dep) {
+                    branch.state.base_commit = branch.merge_base(dep)?;
+                    branch.save_state()?;
+                    println!("  fixed: using the merge base with `{dep}` instead");
+                    fixed += 1;
+                }
+            }
+        } else if let Some(dep) = single_dep {
+            if branch.fork_point(dep)?.is_none() {
+                found += 1;
+                println!(
+                    "branch `{}`: no fork point with `{dep}`, so it cannot be updated",
+                    branch.name()
+                );
+                if let (true, Some(merge_base)) = (fix, branch.merge_base(dep)?) {
+                    println!("  fixed: recorded the merge base {merge_base} as base commit");
+                    branch.state.base_commit = Some(merge_base);
+                    branch.save_state()?;
+                    fixed += 1;
+                }
+            }
+        }
+
+        if branch.state.dirty && !branch.needs_update()? {
+            found += 1;
+            println!(
+                "branch `{}`: marked dirty, but it is up to date",
+                branch.name()
+            );
+            if fix {
+                branch.state.dirty = false;
+                branch.save_state()?;
+                println!("  fixed: cleared the dirty flag");
+                fixed += 1;
+            }
+        }
+    }
+
+    if found == 0 {
+        println!("giddy: no problems found");
+    } else if found > fixed {
+        return Err(anyhow!(
+            "{} problem(s) left{}",
+            found - fixed,
+            if fix {
+                ""
+            } else {
+                ", run `giddy doctor --fix` to fix them"
+            }
+        ));
+    } else {
+        println!("giddy: fixed {fixed} problem(s)");
+    }
+
+    Ok(())
+}
+
+/// Returns the existing branches that the deleted branch `dep` stood for,
+/// following the dependencies recorded in the `orphaned` states.
+fn existing_deps_of(
+    dep: &str,
+    orphaned: &HashMap<String, Vec<String>>,
+    exists: &dyn Fn(&str) -> bool,
+    seen: &mut HashSet<String>,
+) -> Vec<String> {
+    if !seen.insert(dep.to_string()) {
+        return Vec::new();
+    }
+
+    let mut deps = Vec::new();
+    for dep in orphaned.get(dep).into_iter().flatten() {
+        if exists(dep) {
+            deps.push(dep.clone());
+        } else {
+            deps.extend(existing_deps_of(dep, orphaned, exists, seen));
+        }
+    }
+
+    deps
+}
+
+/// Prints the states of all tracked branches as JSON object keyed by branch,
+/// in dependency order.
+fn handle_export(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    let repo = git::Repo::open()?;
+    println!("{}", export_states(&repo)?);
+
+    Ok(())
+}
+
+/// Returns the states of all tracked branches as JSON, as `giddy export`
+/// prints them.
+fn export_states(repo: &git::Repo) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&collect_states(repo)?)?)
+}
+
+/// Returns the states of all tracked branches, in dependency order.
+fn collect_states(repo: &git::Repo) -> Result<IndexMap<String, git::BranchState>> {
+    let graph = repo.graph()?;
+
+    let mut names = graph.stacked_branches(repo.default_branch_name())?;
+    for branch in repo.branches()? {
+        if branch.has_state() && !names.contains(branch.name()) {
+            names.push(branch.name().clone());
+        }
+    }
+
+    let mut states = IndexMap::new();
+    for name in names {
+        let branch = git::Branch::new(&name, repo)?;
+        if branch.has_state() {
+            states.insert(name, branch.state);
+        }
+    }
+
+    Ok(states)
+}
+
+fn handle_gc(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    let repo = git::Repo::open()?;
+    let names = repo.branch_names()?;
+    let exists = |name: &str| names.iter().any(|n| n == name) || repo.is_remote_branch(name);
+    let orphaned = orphaned_deps(&repo)?;
+
+    let mut changed = 0;
+    for mut branch in repo.branches()? {
+        if !reparent_missing_deps(&repo, &mut branch, &orphaned, &exists)? {
+            continue;
+        }
+        changed += 1;
+
+        info!(
+            "giddy: `{}` now depends on {}",
+            branch.name(),
+            branch
+                .state
+                .deps
+                .iter()
+                .map(|dep| format!("`{dep}`"))
+                .join(", ")
+        );
+    }
+
+    for name in orphaned.keys().sorted() {
+        repo.set_state_of(name, None)?;
+        info!("giddy: removed the state of deleted branch `{name}`");
+    }
+
+    if changed == 0 && orphaned.is_empty() {
+        info!("giddy: nothing to clean up");
+    }
+
+    Ok(())
+}
+
+/// Returns the dependencies recorded in the states of deleted branches, by
+/// branch.
+fn orphaned_deps(repo: &git::Repo) -> Result<HashMap<String, Vec<String>>> {
+    Ok(repo
+        .orphaned_states()?
+        .into_iter()
+        .map(|name| {
+            let deps = repo
+                .state_of(&name)
+                .map(|state| state.deps.into_iter().collect())
+                .unwrap_or_default();
+            (name, deps)
+        })
+        .collect())
+}
+
+/// Replaces the dependencies of `branch` that don't exist anymore with what
+/// they depended on (from the `orphaned` states), and a missing base with
+/// the first dependency. Returns whether anything changed.
+fn reparent_missing_deps(
+    repo: &git::Repo,
+    branch: &mut git::Branch,
+    orphaned: &HashMap<String, Vec<String>>,
+    exists: &dyn Fn(&str) -> bool,
+) -> Result<bool> {
+    let has_missing_dep = branch.state.deps.iter().any(|dep| !exists(dep));
+    let base_missing = branch.state.base.as_ref().is_some_and(|base| !exists(base));
+    if !has_missing_dep && !base_missing {
+        return Ok(false);
+    }
+
+    // dependents of deleted branches inherit their dependencies
+    let mut deps = IndexSet::new();
+    for dep in &branch.state.deps {
+        if exists(dep) {
+            deps.insert(dep.clone());
+        } else {
+            deps.extend(existing_deps_of(dep, orphaned, exists, &mut HashSet::new()));
+        }
+    }
+    deps.shift_remove(branch.name());
+    let default_branch = repo.default_branch_name();
+    if deps.is_empty() && branch.name() != &default_branch {
+        deps.insert(default_branch);
+    }
+    branch.state.deps = deps;
+    if base_missing {
+        branch.state.base = branch.state.deps.first().cloned();
+    }
+    branch.save_state()?;
+
+    Ok(true)
+}
+
+fn handle_import(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+
+    match matches.subcommand() {
+        Some(("machete", matches)) => import_machete(&repo, matches),
+        _ => {
+            let file = matches.get_one::<String>("file").unwrap();
+            let contents =
+                std::fs::read_to_string(file).with_context(|| format!("reading `{file}`"))?;
+            import_states(&repo, &contents, &format!("`{file}`"), true)
+        }
+    }
+}
+
+/// Restores the branch states `giddy export` wrote to `contents`, which
+/// came from `source`.
+///
+/// Branches that don't exist here are skipped, as are those that already
+/// have a state unless `overwrite`. Base commits that are not part of the
+/// branch here get recomputed.
+fn import_states(repo: &git::Repo, contents: &str, source: &str, overwrite: bool) -> Result<()> {
+    let states = serde_json::from_str(contents).with_context(|| format!("parsing {source}"))?;
+    import_state_map(repo, states, source, overwrite)
+}
+
+/// Like [`import_states()`], for already parsed `states`.
+fn import_state_map(
+    repo: &git::Repo,
+    states: IndexMap<String, git::BranchState>,
+    source: &str,
+    overwrite: bool,
+) -> Result<()> {
+    let existing = repo.branch_names()?;
+    let mut branches = repo.branches()?;
+    let mut imported = Vec::new();
+    for (name, mut state) in states {
+        let Some(branch) = branches.iter_mut().find(|branch| branch.name() == &name) else {
+            warning!("branch `{name}` does not exist, skipping");
+            continue;
+        };
+        if branch.has_state() && !overwrite {
+            continue;
+        }
+        state
+            .migrate()
+            .with_context(|| format!("importing state of `{name}`"))?;
+        for dep in &state.deps {
+            if !existing.contains(dep) && !repo.is_remote_branch(dep) {
+                warning!("branch `{name}` depends on non-existing branch `{dep}`");
+            }
+        }
+        let base_known = match &state.base_commit {
+            Some(commit) => repo.is_ancestor(commit, &name).unwrap_or(false),
+            None => true,
+        };
+        if !base_known {
+            state.base_commit = match &state.base {
+                Some(base) => repo.merge_base(&name, base)?,
+                None => None,
+            };
+        }
+        branch.state = state;
+        imported.push(name);
+    }
+
+    if let Some(cycle) = graph::dependency_cycle(&branches) {
+        return Err(anyhow!(
+            "importing would create a dependency cycle: {}",
+            cycle.join(" → ")
+        ));
+    }
+
+    for branch in branches
+        .iter_mut()
+        .filter(|branch| imported.contains(branch.name()))
+    {
+        branch.save_state()?;
+    }
+    info!(
+        "giddy: imported {} branch(es) from {source}",
+        imported.len()
+    );
+
+    Ok(())
+}
+
+/// Takes over the branch layout of git-machete.
+fn import_machete(repo: &git::Repo, matches: &clap::ArgMatches) -> Result<()> {
+    let default_branch = repo.default_branch_name();
+
+    let file = matches
+        .get_one::<String>("file")
+        .map(camino::Utf8PathBuf::from)
+        .unwrap_or_else(|| repo.git_dir().join("machete"));
+    let contents = std::fs::read_to_string(&file).with_context(|| format!("reading `{file}`"))?;
+    let entries = machete::parse(&contents).with_context(|| format!("parsing `{file}`"))?;
+
+    let existing = repo.branch_names()?;
+    let mut branches = repo.branches()?;
+    let mut imported = Vec::new();
+    for entry in entries {
+        if !existing.contains(&entry.name) {
+            warning!("branch `{}` does not exist, skipping", entry.name);
+            continue;
+        }
+        let Some(parent) = entry.parent else {
+            if entry.name != default_branch {
+                warning!(
+                    "`{}` is a root, but not the default branch, leaving it alone",
+                    entry.name
+                );
+            }
+            continue;
+        };
+        if !existing.contains(&parent) {
+            continue;
+        }
+
+        let branch = branches
+            .iter_mut()
+            .find(|branch| branch.name() == &entry.name)
+            .unwrap();
+        branch.state.base_commit = repo.merge_base(&entry.name, &parent)?;
+        branch.state.deps = IndexSet::from([parent.clone()]);
+        branch.state.base = Some(parent);
+        if entry.pr.is_some() {
+            branch.state.pr = entry.pr;
+        }
+        imported.push(entry.name);
+    }
+
+    if let Some(cycle) = graph::dependency_cycle(&branches) {
+        return Err(anyhow!(
+            "importing would create a dependency cycle: {}",
+            cycle.join(" → ")
+        ));
+    }
+
+    for branch in branches
+        .iter_mut()
+        .filter(|branch| imported.contains(branch.name()))
+    {
+        branch.save_state()?;
+        info!(
+            "giddy: `{}` now depends on `{}`",
+            branch.name(),
+            branch.deps()[0]
+        );
+    }
+    info!(
+        "giddy: imported {} branch(es) from `{file}`",
+        imported.len()
+    );
+
+    Ok(())
+}
+
+/// Sets giddy up in the repository: creates its directory, records the
+/// default branch in the repository config and offers to track existing
+/// branches.
+fn handle_init(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+
+    let default_branch = match matches.get_one::<String>("default-branch") {
+        Some(name) => repo.resolve_branch(name)?,
+        None => repo.default_branch_name(),
+    };
+    if !repo.branch_names()?.contains(&default_branch) {
+        return Err(giddy::Error::BranchNotFound(default_branch).into());
+    }
+
+    let config_path = config::Config::repo_path(repo.git_dir());
+    if config_path.exists() {
+        info!("giddy: `{config_path}` exists, leaving it alone");
+        if repo.config().default_branch.as_ref() != Some(&default_branch)
+            && matches.get_one::<String>("default-branch").is_some()
+        {
+            warning!("set `default-branch = \"{default_branch}\"` in `{config_path}` yourself");
+        }
+    } else if git::is_dry_run() {
+        println!("giddy: would write `{config_path}` with default branch `{default_branch}`");
+    } else {
+        std::fs::create_dir_all(repo.giddy_dir())
+            .with_context(|| format!("creating `{}`", repo.giddy_dir()))?;
+        std::fs::write(
+            &config_path,
+            format!(
+                "# giddy settings of this repository\ndefault-branch = {}\n",
+                toml::Value::String(default_branch.clone())
+            ),
+        )
+        .with_context(|| format!("writing `{config_path}`"))?;
+        info!("giddy: wrote `{config_path}`, default branch is `{default_branch}`");
+    }
+
+    // the configured default branch is only picked up by a fresh `Repo`
+    let repo = git::Repo::open()?;
+    let untracked = repo
+        .branches()?
+        .into_iter()
+        .filter(|branch| !branch.has_state() && !repo.is_protected(branch.name()))
+        .map(|branch| branch.name().clone())
+        .filter(|name| name != &default_branch)
+        .collect_vec();
+    if untracked.is_empty() {
+        return Ok(());
+    }
+
+    let adopt = if matches.get_flag("adopt") {
+        untracked
+    } else if ui::is_interactive() {
+        ui::pick_many(
+            "existing branches to track",
+            &untracked,
+            &vec![true; untracked.len()],
+        )?
+    } else {
+        info!(
+            "giddy: {} untracked branch(es), track them with `giddy init --adopt` or `giddy track`",
+            untracked.len()
+        );
+        return Ok(());
+    };
+
+    let mut graph = repo.graph()?;
+    for name in &adopt {
+        if let Err(e) = track_branch(&repo, &mut graph, name, None) {
+            warning!("not tracking `{name}`: {e:#}");
+        }
+    }
+
+    Ok(())
+}
+
+/// How long `land` waits for a PR to show up as merged.
+const LAND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+fn handle_land(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let default_branch = repo.default_branch_name();
+    let remote = repo.upstream_remote(&default_branch);
+    let current_branch = repo.branch_current()?;
+
+    let names = match matches.get_one::<String>("branch") {
+        Some(name) => vec![repo.resolve_branch(name)?],
+        None if matches.get_flag("all") => repo
+            .graph()?
+            .get_stack(current_branch.name())?
+            .into_iter()
+            .filter(|name| name != &default_branch && !repo.is_remote_branch(name))
+            .collect(),
+        None => vec![current_branch.name().clone()],
+    };
+    if names.len() > 1 {
+        check_worktree_clean(&repo, false)?;
+    }
+
+    let options = UpdateOptions {
+        push: true,
+        range_diff: false,
+    };
+    for (i, name) in names.iter().enumerate() {
+        land_branch(&repo, name, &default_branch)?;
+
+        info!("giddy: fetching `{remote}`...");
+        repo.cmd_check(["fetch", "--prune", "--quiet", &remote])?
+            .true_or(anyhow!("fetching `{remote}` failed"))?;
+        pull_default_branch(
+            &repo,
+            &default_branch,
+            &format!("{remote}/{default_branch}"),
+        )?;
+        repo.unlink_branch(name)?;
+        repo.delete_branch(name)?;
+
+        // the rest of the stack now goes on top of what just landed
+        for name in &names[i + 1..] {
+            let mut branch = Branch::new(name, &repo)?;
+            update_branch(&repo, &mut branch, &options)?;
+        }
+    }
+
+    let back = current_branch.name();
+    if repo.branch_names()?.contains(back) && repo.branch_current()?.name() != back {
+        repo.switch(back)?;
+    }
+
+    Ok(())
+}
+
+/// Merges the PR of branch `name` into its base and waits for it to show up
+/// as merged.
+///
+/// The branch must depend on nothing but the default branch or a
+/// remote-tracking branch, and its PR must be approved with all checks passed.
+fn land_branch(repo: &git::Repo, name: &str, default_branch: &str) -> Result<()> {
+    let branch = git::Branch::new(name, repo)?;
+    let pr = branch
+        .state
+        .pr
+        .ok_or_else(|| anyhow!("branch `{name}` has no PR, create one with `giddy submit`"))?;
+    let deps = branch.deps();
+    let base = match deps.as_slice() {
+        [dep] if dep == default_branch || repo.is_remote_branch(dep) => repo.forge_base(dep),
+        _ => {
+            return Err(anyhow!(
+                "branch `{name}` depends on {}, land those first (or use `--all`)",
+                deps.iter().map(|dep| format!("`{dep}`")).join(", ")
+            ))
+        }
+    };
+
+    let forge = repo.forge()?;
+    let status = forge.pr_status(pr)?;
+    match status.state.as_str() {
+        "MERGED" => {
+            info!("giddy: PR #{pr} of `{name}` is merged already");
+            return Ok(());
+        }
+        "OPEN" => {}
+        _ => return Err(anyhow!("PR #{pr} of `{name}` is {}", status.summary())),
+    }
+    status.is_ready().true_or(anyhow!(
+        "PR #{pr} of `{name}` is not ready to land: {}",
+        status.summary()
+    ))?;
+
+    hooks::run(
+        repo,
+        hooks::Hook::PreLand,
+        &hooks::Env {
+            branch: name,
+            base: Some(&deps[0]),
+            ..Default::default()
+        },
+    )?;
+
+    // merging lands what was pushed, so that has to be this branch
+    repo.push_branch(name)?;
+    if forge.pr_view(pr)?.base_ref_name != base {
+        forge.set_pr_base(pr, &base)?;
+    }
+
+    info!("giddy: landing `{name}` (PR #{pr})...");
+    forge.merge_pr(pr)?;
+    if git::is_dry_run() {
+        return Ok(());
+    }
+
+    let start = std::time::Instant::now();
+    while forge.pr_state(pr)? != "MERGED" {
+        if start.elapsed() > LAND_TIMEOUT {
+            return Err(anyhow!(
+                "PR #{pr} of `{name}` did not get merged within {} minutes",
+                LAND_TIMEOUT.as_secs() / 60
+            ));
+        }
+        std::thread::sleep(std::time::Duration::from_secs(5));
+    }
+    info!("giddy: landed `{name}`");
+
+    Ok(())
+}
+
+fn handle_new(matches: &clap::ArgMatches) -> Result<()> {
+    let name = matches.get_one::<String>("name");
+    let planned = matches.get_flag("planned");
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let base = match matches.get_one::<String>("from") {
+        Some(from) => {
+            let from = repo.resolve_branch(from)?;
+            repo.branch_head(&from)
+                .with_context(|| format!("branch `{from}` not found"))?;
+            from
+        }
+        None => current_branch.name().clone(),
+    };
+
+    let message = matches.get_one::<String>("message");
+    if message.is_some() {
+        let status = repo.worktree_status()?;
+        (status.staged + status.unstaged + status.untracked > 0)
+            .true_or(anyhow!("no local changes to commit to the new branch"))?;
+    }
+
+    let name = match name {
+        Some(name) => name.clone(),
+        None => naming::new_branch_name(&repo, &base, message.map(String::as_str))?,
+    };
+
+    // building on a planned branch with commits makes it a real one
+    git::Branch::new(&base, &repo)?.save_landed()?;
+
+    if matches.get_flag("below") {
+        return new_below(&repo, current_branch, &name);
+    }
+    if matches.get_flag("insert") {
+        return new_insert(&repo, &current_branch, &name);
+    }
+
+    let mut new_branch = if planned {
+        info!("giddy: creating new planned branch `{name}`");
+        repo.cmd_check(["branch", &name, &base])?
+            .true_or(anyhow!("creating branch failed"))?;
+        let mut branch = git::Branch::new_with_base(&name, &base, &repo)?;
+        branch.state.planned = true;
+        branch
+    } else {
+        info!("giddy: creating new branch `{name}`");
+        repo.branch_create(&name, &base)?
+    };
+
+    info!("giddy: adding `{base}` as dependency of `{name}`");
+    new_branch.state.deps.insert(base.clone());
+    new_branch
+        .save_state()
+        .with_context(|| anyhow!("saving state for branch `{}`", new_branch.name()))?;
+
+    if let Some(message) = message {
+        commit_changes(&repo, message)?;
+    }
+
+    run_post_new_hook(&repo, &name, &base)
+}
+
+/// Switches to the only dependent of the current branch.
+fn handle_next(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let name = current_branch.name();
+    let dependents = repo.graph()?.get_dependents(name)?;
+
+    step_to(&repo, name, &dependents, "dependents")
+}
+
+/// Switches to the only dependency of the current branch.
+fn handle_prev(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let name = current_branch.name();
+    let deps = current_branch.deps();
+
+    step_to(&repo, name, &deps, "dependencies")
+}
+
+/// Switches from `name` to its only neighbor in `choices`, listing them if
+/// there is more than one.
+fn step_to(repo: &git::Repo, name: &str, choices: &[String], what: &str) -> Result<()> {
+    let target = match choices {
+        [] => return Err(anyhow!("branch `{name}` has no {what}")),
+        [target] => target,
+        _ => {
+            return Err(anyhow!(
+                "branch `{name}` has {} {what}, switch to one of them with `giddy co`: {}",
+                choices.len(),
+                choices
+                    .iter()
+                    .map(|choice| format!("`{choice}`"))
+                    .join(", ")
+            ))
+        }
+    };
+    if repo.is_remote_branch(target) {
+        return Err(anyhow!(
+            "`{target}` is a remote-tracking branch, it cannot be switched to"
+        ));
+    }
+
+    repo.switch(target)
+}
+
+fn handle_suggest(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+
+    if plan::UpdatePlan::exists(&repo) {
+        println!(
+            "an update stopped on conflicts: resolve them, then run `giddy update --continue` (or `giddy update --abort`)"
+        );
+        return Ok(());
+    }
+    if repo.rebase_in_progress() {
+        println!(
+            "a rebase is in progress: resolve the conflicts, then run `git rebase --continue`"
+        );
+        return Ok(());
+    }
+
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+    let graph = repo.graph()?;
+
+    let mut stack = graph.get_stack(current_branch.name())?;
+    stack.retain(|name| name != &default_branch);
+
+    let mut suggestions = Vec::new();
+    for name in &stack {
+        let branch = Branch::new(name, &repo)?;
+        if branch.merged().is_ok_and(|merged| merged) {
+            suggestions.push(format!(
+                "branch `{name}` is merged, remove it: `giddy prune` (or `giddy delete {name}`)"
+            ));
+        } else if branch.needs_update()? {
+            suggestions.push(format!(
+                "branch `{name}` is behind its dependencies, restack: `giddy update -r {name}`"
+            ));
+        } else if branch.state.pr.is_none() {
+            suggestions.push(format!(
+                "branch `{name}` has no PR, submit the stack: `giddy submit`"
+            ));
+        }
+    }
+
+    if suggestions.is_empty() {
+        println!("nothing to do, the stack is up to date.");
+    }
+    for suggestion in suggestions {
+        println!("{suggestion}");
+    }
+
+    Ok(())
+}
+
+fn handle_log(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let bold = repo.config().color.enabled();
+    let current_branch = repo.branch_current()?;
+    let name = match matches.get_one::<String>("branch") {
+        Some(branch) => repo.resolve_branch(branch)?,
+        None => current_branch.name().clone(),
+    };
+    let default_branch = repo.default_branch_name();
+
+    let mut stack = repo.graph()?.get_stack(&name)?;
+    stack.retain(|name| name != &default_branch);
+    if stack.is_empty() {
+        return Err(anyhow!("`{name}` is not part of a stack"));
+    }
+
+    let _pager = pager::start();
+
+    // newest on top, like `git log`
+    for (i, name) in stack.iter().rev().enumerate() {
+        let branch = Branch::new(name, &repo)?;
+        let revs = branch.own_commit_revs();
+        let count = repo.cmd_output(
+            ["rev-list", "--count"]
+                .into_iter()
+                .chain(revs.iter().map(String::as_str)),
+        )?;
+        let marker = if name == current_branch.name() {
+            "* "
+        } else {
+            ""
+        };
+        let heading = format!(
+            "{marker}{name} (on `{}`, {} commit(s))",
+            branch.deps().iter().join("`, `"),
+            count.trim()
+        );
+
+        if i > 0 {
+            println!();
+        }
+        if bold {
+            println!("\x1b[1m{heading}\x1b[0m");
+        } else {
+            println!("{heading}");
+        }
+
+        let mut log = repo.git();
+        log.args(["log", "--format=  %C(auto)%h%d %s"])
+            .arg(if bold {
+                "--color=always"
+            } else {
+                "--color=never"
+            });
+        if matches.get_flag("stat") {
+            log.arg("--stat");
+        }
+        log.args(&revs)
+            .status()?
+            .success()
+            .true_or(anyhow!("`git log` failed for branch `{name}`"))?;
+    }
+
+    Ok(())
+}
+
+fn handle_notes(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let _pager = pager::start();
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+
+    let mut branch_names = if matches.get_flag("stack") {
+        repo.graph()?.get_stack(current_branch.name())?
+    } else {
+        vec![current_branch.name().clone()]
+    };
+    branch_names.retain(|name| name != &default_branch);
+
+    println!("# Changes in `{}`", current_branch.name());
+    for name in &branch_names {
+        let branch = Branch::new(name, &repo)?;
+        println!();
+        println!("## {name}");
+        println!();
+        if let Some(description) = branch.description()? {
+            println!("{description}");
+            println!();
+        }
+        for subject in branch.commit_subjects()? {
+            println!("- {subject}");
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_path(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let from = repo.resolve_branch(matches.get_one::<String>("from").unwrap())?;
+    let to = match matches.get_one::<String>("to") {
+        Some(to) => repo.resolve_branch(to)?,
+        None => repo.branch_current()?.name().clone(),
+    };
+
+    let Some(path) = repo.graph()?.path(&from, &to)? else {
+        println!("there is no dependency path between `{from}` and `{to}`");
+        return Ok(());
+    };
+
+    for (i, name) in path.iter().enumerate() {
+        let branch = Branch::new(name, &repo)?;
+        let status = if branch.state.planned {
+            "planned"
+        } else if branch.merged().is_ok_and(|merged| merged) {
+            "merged"
+        } else if branch.needs_update()? {
+            "needs update"
+        } else {
+            "up to date"
+        };
+        let arrow = if i == 0 { "" } else { "→ " };
+        println!("{}{arrow}{name} ({status})", "  ".repeat(i));
+    }
+
+    Ok(())
+}
+
+fn handle_pr(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+
+    match matches.subcommand() {
+        Some(("create", matches)) => {
+            let mut current_branch = repo.branch_current()?;
+            if let Some(pr) = current_branch.state.pr {
+                return Err(anyhow!(
+                    "branch `{}` already has PR #{pr}",
+                    current_branch.name()
+                ));
+            }
+            let base = current_branch
+                .state
+                .base
+                .as_ref()
+                .map(|base| repo.forge_base(base))
+                .ok_or_else(|| anyhow!("branch `{}` has no base", current_branch.name()))?;
+
+            repo.push_branch(current_branch.name())?;
+            let name = current_branch.name();
+            let (pr, url) = repo.forge()?.create_pr(
+                name,
+                &repo.pr_head(name),
+                &base,
+                matches.get_flag("draft"),
+            )?;
+            current_branch.state.pr = Some(pr);
+            current_branch.save_state()?;
+
+            info!(
+                "giddy: created PR #{pr} for `{}`: {url}",
+                current_branch.name()
+            );
+        }
+        Some(("list", _)) => {
+            let default_branch = repo.default_branch_name();
+            let graph = repo.graph()?;
+            let branches = repo.branches()?;
+
+            for pr in repo.forge()?.list_prs()? {
+                let Some(branch) = branches
+                    .iter()
+                    .find(|branch| branch.state.pr == Some(pr.number))
+                    .or_else(|| branches.iter().find(|b| b.name() == &pr.head_ref_name))
+                else {
+                    continue;
+                };
+
+                let stack = graph
+                    .stack_position(branch.name(), &default_branch)
+                    .map(|(root, depth)| format!(" (stack: {root}, depth {depth})"))
+                    .unwrap_or_default();
+                let base = match branch.deps().first().map(|dep| repo.forge_base(dep)) {
+                    Some(dep) if dep != pr.base_ref_name => {
+                        format!(
+                            "base: {}, should be {dep} (needs retarget)",
+                            pr.base_ref_name
+                        )
+                    }
+                    _ => format!("base: {}", pr.base_ref_name),
+                };
+
+                println!(
+                    "#{} {}{stack} {} {base} {}",
+                    pr.number,
+                    branch.name(),
+                    pr.state,
+                    pr.url
+                );
+            }
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Prints `branch [position/size]` for shell prompts, with a `!` after the
+/// size if the branch is known to need an update. Prints nothing outside of
+/// a branch.
+///
+/// Runs on every prompt, so instead of building the graph it only reads the
+/// state files and cached results, and stays silent on errors.
+fn handle_prompt(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+
+    let Ok(repo) = git::Repo::open() else {
+        return Ok(());
+    };
+    let Ok(name) = repo.cmd_output(["branch", "--show-current"]) else {
+        return Ok(());
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(());
+    }
+
+    let stack = prompt_stack(&repo, name).unwrap_or_default();
+    println!("{name}{stack}");
+
+    Ok(())
+}
+
+/// Returns ` [position/size]` of branch `name` in its stack for the prompt,
+/// with a `!` if it needs an update, or `None` if it is in no stack.
+fn prompt_stack(repo: &git::Repo, name: &str) -> Option<String> {
+    let default_branch = repo.default_branch_name();
+    if name == default_branch {
+        return None;
+    }
+    let states: HashMap<String, git::BranchState> = repo
+        .state_names()
+        .ok()?
+        .into_iter()
+        .filter_map(|name| Some((name.clone(), repo.state_of(&name)?)))
+        .collect();
+    let deps_of = |state: &git::BranchState| -> Vec<String> {
+        if state.deps.is_empty() {
+            vec![default_branch.clone()]
+        } else {
+            state.deps.iter().cloned().collect()
+        }
+    };
+
+    // follow the first dependencies down to the default branch
+    let state = states.get(name)?;
+    let mut root = name;
+    let mut depth = 1;
+    loop {
+        let dep = &deps_of(&states[root])[0];
+        if dep == &default_branch {
+            break;
+        }
+        // remote-tracking (or missing) dependencies have no state
+        root = states.get_key_value(dep)?.0;
+        depth += 1;
+        if depth > states.len() {
+            return None;
+        }
+    }
+
+    let mut stack = vec![root];
+    let mut i = 0;
+    while i < stack.len() {
+        for (other, state) in &states {
+            if !stack.contains(&other.as_str()) && state.deps.contains(stack[i]) {
+                stack.push(other);
+            }
+        }
+        i += 1;
+    }
+
+    let stale = !state.planned
+        && repo
+            .cached_needs_update(name, &deps_of(state))
+            .unwrap_or(false);
+    Some(format!(
+        " [{depth}/{}{}]",
+        stack.len(),
+        if stale { "!" } else { "" }
+    ))
+}
+
+fn handle_prune(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let default_branch = repo.default_branch_name();
+
+    let mut merged = Vec::new();
+    for name in repo.graph()?.get_descendants(&default_branch)? {
+        let branch = Branch::new(&name, &repo)?;
+        if branch.state.planned || repo.is_protected(&name) {
+            continue;
+        }
+        let Some(base) = branch.state.base.clone() else {
+            continue;
+        };
+        if !branch.merged_into(&base)? {
+            continue;
+        }
+        // branches without commits yet count as merged, too; fast-forward
+        // merged ones have no commits left on top of their base, but still
+        // moved past where they started
+        let empty = match &branch.state.base_commit {
+            Some(base_commit) => &branch.head()? == base_commit,
+            None => branch.head()? == repo.branch_head(&base)?,
+        };
+        if !empty {
+            merged.push(name);
+        }
+    }
+    if merged.is_empty() {
+        info!("giddy: no merged branches");
+        return Ok(());
+    }
+
+    info!(
+        "giddy: these branches have been merged: {}",
+        merged.iter().map(|name| format!("`{name}`")).join(", ")
+    );
+    if !matches.get_flag("force") && !ui::confirm("delete them?")? {
+        return Ok(());
+    }
+
+    for name in &merged {
+        let remote = repo.push_remote(name);
+        let on_remote = matches.get_flag("remote") && repo.has_upstream(name);
+
+        repo.unlink_branch(name)?;
+        repo.delete_branch(name)?;
+        info!("giddy: deleted branch `{name}`");
+
+        if on_remote {
+            repo.cmd_check(["push", "--quiet", "--delete", &remote, name])?
+                .true_or(anyhow!("deleting `{name}` on `{remote}` failed"))?;
+            info!("giddy: deleted branch `{name}` on `{remote}`");
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_push(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+
+    for name in repo.graph()?.get_stack(current_branch.name())? {
+        if name == default_branch || Branch::new(&name, &repo)?.state.planned {
+            continue;
+        }
+        repo.push_branch(&name)?;
+    }
+
+    Ok(())
+}
+
+fn handle_rename(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+    let old: &String = matches.get_one("old").unwrap();
+    let new: &String = matches.get_one("new").unwrap();
+
+    let old = repo.resolve_branch(old)?;
+    repo.graph()?.branch_id(&old)?;
+    if repo.branch_names()?.contains(new) {
+        return Err(anyhow!("branch `{new}` already exists"));
+    }
+
+    repo.rename_branch(&old, new)?;
+    info!("giddy: renamed branch `{old}` to `{new}`");
+
+    Ok(())
+}
+
+fn handle_reorder(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+    let graph = repo.graph()?;
+
+    // the linear stack around the current branch, from the bottom up
+    let mut stack = vec![current_branch.name().clone()];
+    loop {
+        let deps = graph.get_dependencies(&stack[0])?;
+        match deps.as_slice() {
+            [dep] if dep != &default_branch => stack.insert(0, dep.clone()),
+            [_] => break,
+            _ => {
+                return Err(anyhow!(
+                    "branch `{}` does not have a single dependency, cannot reorder its stack",
+                    stack[0]
+                ))
+            }
+        }
+    }
+    loop {
+        let dependents = graph.get_dependents(stack.last().unwrap())?;
+        match dependents.as_slice() {
+            [dependent] if graph.get_dependencies(dependent)?.len() == 1 => {
+                stack.push(dependent.clone())
+            }
+            _ => break,
+        }
+    }
+    if stack.len() < 2 {
+        return Err(anyhow!(
+            "the stack of `{}` has a single branch, nothing to reorder",
+            current_branch.name()
+        ));
+    }
+
+    let order: Vec<String> = match matches.get_many::<String>("branch") {
+        Some(names) => {
+            let order: Vec<String> = names
+                .map(|name| repo.resolve_branch(name))
+                .collect::<Result<_>>()?;
+            if order.len() != stack.len() || !stack.iter().all(|name| order.contains(name)) {
+                return Err(anyhow!(
+                    "the new order must contain each of {} exactly once",
+                    stack.iter().map(|name| format!("`{name}`")).join(", ")
+                ));
+            }
+            order
+        }
+        None => ui::sort("new order, from the bottom up", &stack)?
+            .into_iter()
+            .map(|index| stack[index].clone())
+            .collect(),
+    };
+    if order == stack {
+        info!("giddy: order unchanged");
+        return Ok(());
+    }
+
+    // where each branch's commits start, before anything moves
+    let mut original = IndexMap::new();
+    let mut starts = IndexMap::new();
+    for name in &stack {
+        let branch = Branch::new(name, &repo)?;
+        let start = match &branch.state.base_commit {
+            Some(base_commit) => base_commit.clone(),
+            None => repo.branch_head(&branch.deps()[0])?,
+        };
+        starts.insert(name.clone(), start);
+        original.insert(
+            name.clone(),
+            plan::Snapshot {
+                head: branch.head()?,
+                state: branch.has_state().then(|| branch.state.clone()),
+            },
+        );
+    }
+
+    let mut below = graph.get_dependencies(&stack[0])?.remove(0);
+    let result = order.iter().try_for_each(|name| {
+        let mut branch = Branch::new(name, &repo)?;
+        info!("giddy: moving `{name}` onto `{below}`");
+        branch.rebase_onto(&starts[name], &below)?;
+        branch.state.deps = IndexSet::from([below.clone()]);
+        branch.state.base = Some(below.clone());
+        branch.state.base_commit = Some(repo.branch_head(&below)?);
+        branch.state.dirty = false;
+        branch.save_state()?;
+        below = name.clone();
+        Ok::<_, anyhow::Error>(())
+    });
+
+    if let Err(e) = result {
+        if repo.rebase_in_progress() {
+            repo.cmd_check(["rebase", "--abort"])?;
+        }
+        for (name, snapshot) in &original {
+            if repo.branch_head(name)? != snapshot.head {
+                repo.reset_branch(name, &snapshot.head)?;
+            }
+            repo.set_state_of(name, snapshot.state.as_ref())?;
+        }
+        if repo.branch_current()?.name() != current_branch.name() {
+            repo.switch(current_branch.name())?;
+        }
+        return Err(e.context("reordering failed, the branches are left as they were"));
+    }
+
+    // branches stacked on top of the reordered ones need to follow
+    let options = UpdateOptions::default();
+    for name in graph.get_descendants(&stack[0])? {
+        if !stack.contains(&name) {
+            let mut branch = Branch::new(&name, &repo)?;
+            update_branch(&repo, &mut branch, &options)?;
+        }
+    }
+    if repo.branch_current()?.name() != current_branch.name() {
+        repo.switch(current_branch.name())?;
+    }
+    info!("giddy: new order: {}", order.join(" → "));
+
+    Ok(())
+}
+
+fn handle_roots(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let _pager = pager::start();
+
+    let repo = git::Repo::open()?;
+    let graph = repo.graph()?;
+    let default_branch = repo.default_branch_name();
+
+    for root in graph.get_dependents(&default_branch)? {
+        // branches merging other branches are part of their stacks
+        if Branch::new(&root, &repo)?.deps().len() > 1 {
+            continue;
+        }
+        let descendants = graph.get_descendants(&root)?;
+        let mut stale = 0;
+        for branch_name in std::iter::once(&root).chain(descendants.iter()) {
+            if Branch::new(branch_name, &repo)?.needs_update()? {
+                stale += 1;
+            }
+        }
+
+        if matches.get_flag("porcelain") {
+            println!("{root}\t{}\t{stale}", descendants.len());
+        } else {
+            println!(
+                "{root}: {} descendant(s), {stale} branch(es) need update",
+                descendants.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates branch `name` between `current_branch` and its dependencies.
+fn new_below(repo: &git::Repo, mut current_branch: git::Branch, name: &str) -> Result<()> {
+    let deps = current_branch.deps();
+    let Some(base) = deps.first() else {
+        return Err(anyhow!(
+            "branch `{}` has no dependencies to insert a branch above",
+            current_branch.name()
+        ));
+    };
+
+    info!("giddy: creating new branch `{name}` on `{base}`");
+    repo.cmd_check(["branch", name, base])?
+        .true_or(anyhow!("creating branch failed"))?;
+    let mut new_branch = git::Branch::new_with_base(name, base, repo)?;
+    new_branch.state.deps = deps.iter().cloned().collect();
+    new_branch
+        .save_state()
+        .with_context(|| anyhow!("saving state for branch `{name}`"))?;
+
+    info!(
+        "giddy: making `{name}` the only dependency of `{}`",
+        current_branch.name()
+    );
+    current_branch.state.deps = IndexSet::from([name.to_string()]);
+    current_branch.save_state()?;
+    let options = UpdateOptions::default();
+    if update_branch(repo, &mut current_branch, &options)? {
+        let current = [current_branch.name().clone()];
+        restack_stale_dependents(repo, &current, &current, &options)?;
+    }
+
+    repo.switch(name)?;
+
+    run_post_new_hook(repo, name, base)
+}
+
+/// Commits the staged changes with `message`, or all local changes if
+/// nothing is staged.
+fn commit_changes(repo: &git::Repo, message: &str) -> Result<()> {
+    if repo.worktree_status()?.staged == 0 {
+        repo.cmd_check(["add", "--all"])?
+            .true_or(anyhow!("staging changes failed"))?;
+    }
+
+    repo.cmd_check(["commit", "--quiet", "--message", message])?
+        .true_or(anyhow!("committing changes failed"))
+}
+
+/// Creates branch `name` on top of `current_branch`, taking over its
+/// dependents.
+///
+/// The new branch starts at the same commit, so the dependents need no
+/// rebase, only their state changes.
+fn new_insert(repo: &git::Repo, current_branch: &git::Branch, name: &str) -> Result<()> {
+    let current = current_branch.name();
+    let dependents = repo.graph()?.get_dependents(current)?;
+
+    info!("giddy: creating new branch `{name}` on `{current}`");
+    repo.cmd_check(["branch", name])?
+        .true_or(anyhow!("creating branch failed"))?;
+    let mut new_branch = git::Branch::new_with_base(name, current, repo)?;
+    new_branch.state.deps.insert(current.clone());
+    new_branch
+        .save_state()
+        .with_context(|| anyhow!("saving state for branch `{name}`"))?;
+
+    // their PRs get retargeted to it, so it has to exist on the forge
+    let mut pushed = false;
+    for dependent in &dependents {
+        info!("giddy: making `{dependent}` depend on `{name}` instead of `{current}`");
+        let mut branch = git::Branch::new(dependent, repo)?;
+        branch.state.deps = branch
+            .state
+            .deps
+            .iter()
+            .map(|dep| {
+                if dep == current {
+                    name.to_string()
+                } else {
+                    dep.clone()
+                }
+            })
+            .collect();
+        if branch.state.base.as_ref() == Some(current) {
+            branch.state.base = Some(name.to_string());
+        }
+        branch.save_state()?;
+
+        if branch.state.pr.is_some() && branch.state.base.as_deref() == Some(name) {
+            if !pushed {
+                repo.push_branch(name)?;
+                pushed = true;
+            }
+            branch.retarget_pr(name);
+        }
+    }
+
+    repo.switch(name)?;
+
+    run_post_new_hook(repo, name, current)
+}
+
+/// Runs the `post-new` hook for the freshly created branch `name`.
+fn run_post_new_hook(repo: &git::Repo, name: &str, base: &str) -> Result<()> {
+    // (there is no branch in dry-run mode)
+    let head = repo.branch_head(name).ok();
+    hooks::run(
+        repo,
+        hooks::Hook::PostNew,
+        &hooks::Env {
+            branch: name,
+            base: Some(base),
+            new_head: head.as_deref(),
+            ..Default::default()
+        },
+    )
+}
+
+fn handle_share(matches: &clap::ArgMatches) -> Result<()> {
+    let repo = git::Repo::open()?;
+
+    let (command, matches) = matches.subcommand().unwrap();
+    let remote = match matches.get_one::<String>("remote") {
+        Some(remote) => remote.clone(),
+        None => repo.push_remote(&repo.default_branch_name()),
+    };
+    match command {
+        "push" => {
+            // keep what others shared, replacing only the states of our branches
+            let shared = !repo
+                .cmd_output(["ls-remote", &remote, SHARE_REF])?
+                .is_empty();
+            let mut states = IndexMap::new();
+            let mut parent = None;
+            if shared {
+                states = serde_json::from_str(&fetch_shared_states(&repo, &remote)?)
+                    .with_context(|| format!("parsing `{SHARE_REF}` of `{remote}`"))?;
+                parent = Some(repo.branch_head(SHARE_REF)?);
+            }
+            states.extend(collect_states(&repo)?);
+
+            repo.write_ref_file(
+                SHARE_REF,
+                "state.json",
+                &serde_json::to_string_pretty(&states)?,
+                parent.as_deref(),
+            )?;
+            repo.cmd_check([
+                "push",
+                "--quiet",
+                &remote,
+                &format!("{SHARE_REF}:{SHARE_REF}"),
+            ])?
+            .true_or(anyhow!(
+                "pushing `{SHARE_REF}` to `{remote}` failed (if someone else shared meanwhile, just try again)"
+            ))?;
+            info!("giddy: shared the branch states on `{remote}`");
+        }
+        "pull" => {
+            let contents = fetch_shared_states(&repo, &remote)?;
+            import_states(
+                &repo,
+                &contents,
+                &format!("`{remote}`"),
+                matches.get_flag("overwrite"),
+            )?;
+        }
+        _ => unreachable!(),
+    }
+
+    Ok(())
+}
+
+/// Fetches the branch states shared on `remote`, returning them as JSON.
+fn fetch_shared_states(repo: &git::Repo, remote: &str) -> Result<String> {
+    // fetch even in dry-run mode, to show what would be imported
+    repo.git()
+        .args([
+            "fetch",
+            "--quiet",
+            remote,
+            &format!("+{SHARE_REF}:{SHARE_REF}"),
+        ])
+        .status()?
+        .success()
+        .true_or(anyhow!("fetching `{SHARE_REF}` from `{remote}` failed"))?;
+    repo.read_ref_file(SHARE_REF, "state.json")
+}
+
+fn handle_show(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    let repo = git::Repo::open()?;
+
+    let remote = matches.get_flag("remote");
+    if matches.get_flag("porcelain") {
+        let names = repo.graph()?.stacked_branches(repo.default_branch_name())?;
+        return print_porcelain(&repo, &names);
+    }
+    if matches.get_flag("all") {
+        return show_all(&repo, remote);
+    }
+    if matches.get_flag("mermaid") {
+        print!("{}", repo.graph()?.to_mermaid());
+        return Ok(());
+    }
+
+    let _pager = matches.get_flag("tree").then(pager::start).flatten();
+
+    let branch = match matches.get_one::<String>("branch") {
+        Some(name) => named_branch(&repo, name)?,
+        None => target_branch(&repo, matches)?,
+    };
+    let is_current = repo
+        .branch_current()
+        .is_ok_and(|current| current.name() == branch.name());
+    let default_branch = repo.branch_default()?;
+    let base_branch = branch.state.base.as_ref();
+
+    println!("git dir: {}", repo.git_dir());
+    println!(
+        "{}: {} (parent: {}{})",
+        if is_current {
+            "current branch"
+        } else {
+            "branch"
+        },
+        branch.name(),
+        base_branch.unwrap_or(&String::from("none")),
+        if branch.merged().is_ok_and(|merged| merged) {
+            " (merged)"
+        } else if branch.equal(default_branch.name())? {
+            " (equal)"
+        } else if branch.state.dirty {
+            " (dirty)"
+        } else if branch.state.planned {
+            " (planned)"
+        } else {
+            ""
+        }
+    );
+
+    println!("  needs update: {}", branch.needs_update()?);
+    if let Some(dep) = branch.deps().first() {
+        let (ahead, behind) = repo.ahead_behind(branch.name(), dep)?;
+        println!("  ahead/behind: {ahead} ahead of `{dep}`, {behind} behind");
+    }
+    if let Some((root, depth)) = repo
+        .graph()?
+        .stack_position(branch.name(), default_branch.name())
+    {
+        println!("         stack: `{root}`, depth {depth}");
+    }
+    if is_current {
+        let status = repo.worktree_status()?;
+        println!(
+            "  working tree: {} staged, {} unstaged, {} untracked, {} stash entries",
+            status.staged,
+            status.unstaged,
+            status.untracked,
+            repo.stash_count(branch.name())?
+        );
+    }
+    if !branch.state.deps.is_empty() {
+        println!("          deps: {}", branch.state.deps.iter().join(", "));
+    }
+    if let Some(pr) = branch.state.pr {
+        if remote {
+            println!("            PR: #{pr} ({})", remote_pr_summary(&repo, pr));
+        } else {
+            println!("            PR: #{pr}");
+        }
+    }
+
+    println!("default branch: {}", default_branch.name());
+
+    if matches.get_flag("tree") {
+        let graph = repo.graph()?;
+        use ptree::graph::print_graph;
+
+        let branches = repo.branches()?;
+        let planned: HashSet<String> = branches
+            .iter()
+            .filter(|branch| branch.state.planned)
+            .map(|branch| branch.name().clone())
+            .collect();
+        let prs: HashMap<String, String> = if remote {
+            branches
+                .iter()
+                .filter_map(|branch| Some((branch.name().clone(), branch.state.pr?)))
+                .map(|(name, pr)| (name, format!("PR #{pr}: {}", remote_pr_summary(&repo, pr))))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let dim = repo.config().color.enabled();
+
+        let graph = graph.reversed();
+        let branch_id = *graph.branch_id(default_branch.name())?;
+        let remote_ids = graph
+            .remotes()
+            .iter()
+            .map(|name| graph.branch_id(name).copied())
+            .collect::<Result<Vec<_>>>()?;
+        let graph = graph.graph.into_inner().map(
+            |_, name| {
+                let label = match prs.get(name) {
+                    Some(pr) => format!("{name} ({pr})"),
+                    None => name.clone(),
+                };
+                match (planned.contains(name), dim) {
+                    (true, true) => format!("\x1b[2m{label} (planned)\x1b[0m"),
+                    (true, false) => format!("{label} (planned)"),
+                    (false, _) => label,
+                }
+            },
+            |_, edge| *edge,
+        );
+
+        print_graph(&graph, branch_id)?;
+        for remote_id in remote_ids {
+            print_graph(&graph, remote_id)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the forge's view of PR `pr`, or why it couldn't be looked up.
+fn remote_pr_summary(repo: &git::Repo, pr: u32) -> String {
+    match repo.forge().and_then(|forge| forge.pr_status(pr)) {
+        Ok(status) => status.summary(),
+        Err(e) => format!("unknown: {e:#}"),
+    }
+}
+
+/// Prints a one-line status of every branch, in dependency order.
+fn show_all(repo: &git::Repo, remote: bool) -> Result<()> {
+    use git::Branch;
+
+    let _pager = pager::start();
+
+    let default_branch = repo.default_branch_name();
+    let names = repo.graph()?.stacked_branches(&default_branch)?;
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or_default();
+
+    for name in names {
+        let branch = Branch::new(&name, repo)?;
+        let status = branch_status(repo, &branch, &default_branch, remote)?;
+        println!("{name:width$}  {}", status.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Prints one `--porcelain` record per branch in `names`.
+///
+/// The format is documented with the `--porcelain` flags (see
+/// [`cli::PORCELAIN_BRANCH_FORMAT`]), so it must not change.
+fn print_porcelain(repo: &git::Repo, names: &[String]) -> Result<()> {
+    use git::Branch;
+
+    let default_branch = repo.default_branch_name();
+    let current = repo.branch_current().ok();
+    for name in names {
+        let branch = Branch::new(name, repo)?;
+        let state = if branch.state.planned {
+            "planned"
+        } else if branch.needs_update()? {
+            "needs-update"
+        } else {
+            "up-to-date"
+        };
+        let merged = !branch.state.planned && branch.merged_into(&default_branch)?;
+        let is_current = current
+            .as_ref()
+            .is_some_and(|current| current.name() == name);
+        let fields = [
+            name.clone(),
+            branch.state.base.clone().unwrap_or_else(|| "-".into()),
+            state.into(),
+            if merged { "merged" } else { "-" }.into(),
+            if branch.state.dirty { "dirty" } else { "-" }.into(),
+            branch
+                .state
+                .pr
+                .map_or_else(|| "-".into(), |pr| pr.to_string()),
+            if is_current { "current" } else { "-" }.into(),
+        ];
+        println!("{}", fields.join("\t"));
+    }
+
+    Ok(())
+}
+
+/// Returns the parts of a branch's one-line status, as printed by `show -a`.
+fn branch_status(
+    repo: &git::Repo,
+    branch: &git::Branch,
+    default_branch: &str,
+    remote: bool,
+) -> Result<Vec<String>> {
+    let mut status = vec![format!(
+        "base: {}",
+        branch.state.base.as_deref().unwrap_or("none")
+    )];
+    if branch.state.planned {
+        status.push("planned".into());
+    } else if branch.needs_update()? {
+        status.push("needs update".into());
+    } else {
+        status.push("up to date".into());
+    }
+    if !branch.state.planned && branch.merged_into(default_branch)? {
+        status.push("merged".into());
+    }
+    if branch.state.dirty {
+        status.push("dirty".into());
+    }
+    match branch.state.pr {
+        Some(pr) if remote => status.push(format!("PR #{pr} ({})", remote_pr_summary(repo, pr))),
+        Some(pr) => status.push(format!("PR #{pr}")),
+        None => {}
+    }
+
+    Ok(status)
+}
+
+fn handle_split(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let mut current_branch = repo.branch_current()?;
+    let name = current_branch.name().clone();
+    let deps = current_branch.deps();
+    if deps.is_empty() {
+        return Err(anyhow::Error::from(giddy::Error::NotTracked(name)).context("nothing to split"));
+    }
+
+    let mut range = vec![name.clone()];
+    match &current_branch.state.base_commit {
+        Some(base_commit) => range.push(format!("^{base_commit}")),
+        None => range.extend(deps.iter().map(|dep| format!("^{dep}"))),
+    }
+    let mut args = vec!["rev-list".to_string(), "--merges".to_string()];
+    args.extend(range.iter().cloned());
+    if !repo.cmd_output_vec(args)?.is_empty() {
+        return Err(anyhow!(
+            "branch `{name}` contains merge commits, cannot split it"
+        ));
+    }
+
+    let mut args = vec![
+        "log".to_string(),
+        "--reverse".to_string(),
+        "--format=%H %h %s".to_string(),
+    ];
+    args.extend(range);
+    // full hash, and what to show the user
+    let commits = repo
+        .cmd_output_vec(args)?
+        .into_iter()
+        .filter_map(|line| {
+            let (hash, rest) = line.split_once(' ')?;
+            Some((hash.to_string(), rest.to_string()))
+        })
+        .collect_vec();
+    if commits.len() < 2 {
+        return Err(anyhow!(
+            "branch `{name}` needs at least two commits to be split"
+        ));
+    }
+
+    // the last commit of each new branch, in order
+    let split_points: Vec<String> = match matches.get_many::<String>("at") {
+        Some(commits) => commits
+            .map(|commit| repo.branch_head(format!("{commit}^{{commit}}")))
+            .collect::<Result<_>>()?,
+        None => {
+            // the last commit stays on the current branch anyway
+            let items = commits[..commits.len() - 1]
+                .iter()
+                .map(|(_, line)| line.clone())
+                .collect_vec();
+            let picked = ui::pick_many(
+                &format!("commits of `{name}` that end a new branch"),
+                &items,
+                &vec![false; items.len()],
+            )?;
+            commits
+                .iter()
+                .filter(|(_, line)| picked.contains(line))
+                .map(|(hash, _)| hash.clone())
+                .collect()
+        }
+    };
+    let mut indices = Vec::new();
+    for split_point in &split_points {
+        match commits.iter().position(|(hash, _)| hash == split_point) {
+            Some(index) if index + 1 < commits.len() => indices.push(index),
+            Some(_) => {
+                return Err(anyhow!(
+                    "cannot split at the last commit of `{name}`, it stays on `{name}`"
+                ))
+            }
+            None => {
+                return Err(anyhow!(
+                    "commit {split_point} is not one of `{name}`'s own commits"
+                ))
+            }
+        }
+    }
+    indices.sort();
+    indices.dedup();
+    let split_points = indices
+        .iter()
+        .map(|index| commits[*index].0.clone())
+        .collect_vec();
+    if split_points.is_empty() {
+        return Err(anyhow!("no commits picked, nothing to split"));
+    }
+
+    let mut names: Vec<String> = matches
+        .get_many::<String>("name")
+        .map(|names| names.cloned().collect())
+        .unwrap_or_default();
+    if names.len() > split_points.len() {
+        return Err(anyhow!(
+            "got {} names for {} new branches",
+            names.len(),
+            split_points.len()
+        ));
+    }
+    let existing = repo.branch_names()?;
+    for n in names.len()..split_points.len() {
+        let default = format!("{name}-{}", n + 1);
+        names.push(if ui::is_interactive() {
+            ui::input(&format!("name of branch {}", n + 1), &default)?
+        } else {
+            default
+        });
+    }
+    for new_name in &names {
+        if existing.contains(new_name) || names.iter().filter(|n| n == &new_name).count() > 1 {
+            return Err(anyhow!("branch `{new_name}` already exists"));
+        }
+    }
+
+    let mut below_deps = current_branch.state.deps.clone();
+    let mut below_commit = current_branch.state.base_commit.clone();
+    let mut first = 0;
+    for ((new_name, split_point), index) in names.iter().zip(&split_points).zip(&indices) {
+        repo.cmd_check(["branch", new_name, split_point])?
+            .true_or(anyhow!("creating branch `{new_name}` failed"))?;
+
+        let mut branch = Branch::new(new_name, &repo)?;
+        branch.state = git::BranchState::default();
+        branch.state.base = below_deps.first().cloned();
+        branch.state.deps = below_deps;
+        branch.state.base_commit = below_commit;
+        branch.save_state()?;
+        info!(
+            "giddy: created branch `{new_name}` with {} commit(s)",
+            index + 1 - first
+        );
+
+        below_deps = IndexSet::from([new_name.clone()]);
+        below_commit = Some(split_point.clone());
+        first = index + 1;
+    }
+
+    current_branch.state.base = Some(names.last().unwrap().clone());
+    current_branch.state.deps = below_deps;
+    current_branch.state.base_commit = below_commit;
+    current_branch.save_state()?;
+    info!("giddy: `{name}` now depends on `{}`", names.last().unwrap());
+
+    Ok(())
+}
+
+/// Prints the status of every branch in the current stack: its dependencies,
+/// the branch itself and its descendants.
+fn handle_status(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let current = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+    let names = repo
+        .graph()?
+        .get_stack(current.name())?
+        .into_iter()
+        .filter(|name| name != &default_branch)
+        .collect_vec();
+    if matches.get_flag("porcelain") {
+        return print_porcelain(&repo, &names);
+    }
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or_default();
+
+    let mut stale = 0;
+    for name in names {
+        let branch = Branch::new(&name, &repo)?;
+        let mut status =
+            branch_status(&repo, &branch, &default_branch, matches.get_flag("remote"))?;
+        if !branch.state.planned && branch.needs_update()? {
+            stale += 1;
+        }
+        let marker = if &name == current.name() {
+            let worktree = repo.worktree_status()?;
+            if worktree.staged + worktree.unstaged > 0 {
+                status.push("local changes".into());
+            }
+            '*'
+        } else {
+            ' '
+        };
+
+        println!("{marker} {name:width$}  {}", status.join(", "));
+    }
+
+    if stale > 0 {
+        info!("giddy: {stale} branch(es) need update, run `giddy update -r -d`");
+    }
+
+    Ok(())
+}
+
+fn handle_submit(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let draft = matches.get_flag("draft");
+    let repo = git::Repo::open()?;
+    let current_branch = repo.branch_current()?;
+    let default_branch = repo.default_branch_name();
+
+    if repo.config().forge == Some(config::ForgeKind::Gerrit) {
+        return submit_to_gerrit(&repo, current_branch.name());
+    }
+
+    let forge = repo.forge()?;
+    for name in repo.graph()?.get_stack(current_branch.name())? {
+        let mut branch = Branch::new(&name, &repo)?;
+        if name == default_branch || repo.is_remote_branch(&name) || branch.state.planned {
+            continue;
+        }
+        branch.save_landed()?;
+        let base = repo.forge_base(&branch.deps()[0]);
+
+        repo.push_branch(&name)?;
+        match branch.state.pr {
+            Some(pr) => {
+                if forge.pr_view(pr)?.base_ref_name != base {
+                    forge.set_pr_base(pr, &base)?;
+                    info!("giddy: retargeted PR #{pr} of `{name}` to `{base}`");
+                }
+            }
+            None => {
+                let (pr, url) = forge.create_pr(&name, &repo.pr_head(&name), &base, draft)?;
+                branch.state.pr = Some(pr);
+                branch.save_state()?;
+                info!("giddy: created PR #{pr} for `{name}`: {url}");
+            }
+        }
+    }
+
+    navigation::refresh(&repo, std::slice::from_ref(current_branch.name()))
+}
+
+/// Sends the stack of `branch` to gerrit for review, bottom to top.
+fn submit_to_gerrit(repo: &git::Repo, branch: &str) -> Result<()> {
+    let default_branch = repo.default_branch_name();
+    let mut stack = repo.graph()?.get_stack(branch)?;
+    stack.retain(|name| {
+        name != &default_branch
+            && !repo.is_remote_branch(name)
+            && !git::Branch::new(name, repo).is_ok_and(|b| b.state.planned)
+    });
+    for name in &stack {
+        if git::Branch::new(name, repo)?.deps().len() > 1 {
+            return Err(anyhow!(
+                "branch `{name}` has several dependencies, gerrit needs a linear history"
+            ));
+        }
+    }
+
+    let rewritten = gerrit::add_change_ids(repo, &stack)?;
+    for name in &stack {
+        gerrit::push_for_review(repo, name, &default_branch)?;
+    }
+
+    restack_stale_dependents(repo, &rewritten, &stack, &UpdateOptions::default())
+}
+
+fn handle_sync(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let default_branch = repo.default_branch_name();
+    let remote = repo.upstream_remote(&default_branch);
+    let autostash = check_worktree_clean(
+        &repo,
+        matches.get_flag("autostash") || repo.config().autostash,
+    )?;
+
+    info!("giddy: fetching `{remote}`...");
+    repo.cmd_check(["fetch", "--prune", "--quiet", &remote])?
+        .true_or(anyhow!("fetching `{remote}` failed"))?;
+
+    let upstream = format!("{remote}/{default_branch}");
+    if matches.get_flag("pull") {
+        pull_default_branch(&repo, &default_branch, &upstream)?;
+    }
+
+    for branch in repo.branches()? {
+        if repo.is_protected(branch.name()) || branch.state.planned || !branch.has_own_commits()? {
+            continue;
+        }
+        let merged = branch.merged_into(&upstream)?
+            || branch.state.pr.is_some_and(|pr| {
+                repo.forge()
+                    .and_then(|forge| forge.pr_state(pr))
+                    .is_ok_and(|state| state == "MERGED")
+            });
+        if !merged {
+            continue;
+        }
+
+        info!("giddy: `{}` has been merged, removing it", branch.name());
+        repo.unlink_branch(branch.name())?;
+        repo.delete_branch(branch.name())?;
+    }
+
+    let current_branch = repo.branch_current()?;
+    let stash = if autostash { repo.stash_push()? } else { None };
+    let options = UpdateOptions::default();
+    for name in repo.graph()?.stacked_branches(&default_branch)? {
+        let mut branch = Branch::new(&name, &repo)?;
+        update_branch(&repo, &mut branch, &options)?;
+    }
+
+    if repo.branch_current()?.name() != current_branch.name() {
+        repo.switch(current_branch.name())?;
+    }
+    if let Some(stash) = stash {
+        repo.stash_pop(&stash)?;
+    }
+
+    Ok(())
+}
+
+/// Fast-forwards `default_branch` to `upstream`, failing if they diverged.
+fn pull_default_branch(repo: &git::Repo, default_branch: &str, upstream: &str) -> Result<()> {
+    if repo.is_ancestor(upstream, default_branch)? {
+        return Ok(());
+    }
+    repo.is_ancestor(default_branch, upstream)?
+        .true_or(anyhow!(
+            "`{default_branch}` has diverged from `{upstream}`, cannot fast-forward it"
+        ))?;
+
+    info!("giddy: fast-forwarding `{default_branch}` to `{upstream}`...");
+    repo.fast_forward(default_branch, upstream)
+}
+
+fn handle_track(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+
+    let repo = git::Repo::open()?;
+    let default_branch = repo.default_branch_name();
+    let base: Option<String> = matches
+        .get_one::<String>("base")
+        .map(|base| repo.resolve_branch(base))
+        .transpose()?;
+
+    let names: Vec<String> = match matches.get_many::<String>("branch") {
+        Some(names) => names
+            .map(|name| repo.resolve_branch(name))
+            .collect::<Result<_>>()?,
+        None => vec![repo.branch_current()?.name().clone()],
+    };
+
+    let mut graph = repo.graph()?;
+    for name in &names {
+        if name == &default_branch {
+            return Err(anyhow!(
+                "`{name}` is the default branch, it cannot be tracked"
+            ));
+        }
+        let branch = Branch::new(name, &repo)?;
+        if branch.has_state() && !branch.state.deps.is_empty() {
+            info!(
+                "giddy: `{name}` is already tracked (depends on {})",
+                branch
+                    .deps()
+                    .iter()
+                    .map(|dep| format!("`{dep}`"))
+                    .join(", ")
+            );
+            continue;
+        }
+
+        track_branch(&repo, &mut graph, name, base.as_deref())?;
+    }
+
+    Ok(())
+}
+
+/// Starts tracking branch `name` on top of `base`, or the base inferred from
+/// the history.
+fn track_branch(
+    repo: &git::Repo,
+    graph: &mut graph::GraphRepo,
+    name: &str,
+    base: Option<&str>,
+) -> Result<()> {
+    let base = match base {
+        Some(base) => base.to_string(),
+        None => repo.get_base_branch(name)?,
+    };
+    let base_commit = repo
+        .merge_base(name, &base)?
+        .ok_or_else(|| anyhow!("`{name}` and `{base}` have no common history"))?;
+
+    graph.try_add_dep(name, &base)?;
+    let mut branch = git::Branch::new(name, repo)?;
+    branch.state.deps = IndexSet::from([base.clone()]);
+    branch.state.base = Some(base.clone());
+    branch.state.base_commit = Some(base_commit);
+    branch.save_state()?;
+    info!("giddy: tracking `{name}` on top of `{base}`");
+
+    Ok(())
+}
+
+fn handle_tui(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+
+    #[cfg(feature = "tui")]
+    return tui::run();
+
+    #[cfg(not(feature = "tui"))]
+    Err(anyhow!("giddy was built without the `tui` feature"))
+}
+
+fn handle_undo(matches: &clap::ArgMatches) -> Result<()> {
+    let _ = matches;
+    let repo = git::Repo::open()?;
+
+    if plan::UpdatePlan::exists(&repo) {
+        return Err(anyhow!(
+            "an update is in progress, use `giddy update --abort` to roll it back"
+        ));
+    }
+
+    let mut journal = journal::Journal::load(&repo)?;
+    let Some(entry) = journal.pop() else {
+        return Err(anyhow!("nothing to undo"));
+    };
+
+    info!("giddy: undoing `giddy {}`", entry.command);
+    journal::restore(&repo, &entry)?;
+    journal.save(&repo)
+}
+
+fn handle_update(matches: &clap::ArgMatches) -> Result<()> {
+    use git::Branch;
+    use plan::{Snapshot, UpdatePlan};
+
+    let all = matches.get_flag("all");
+    let recursive = matches.get_flag("recursive");
+    let onto: Option<&String> = matches.get_one("onto");
+    let mut repo = git::Repo::open()?;
+    if matches.get_flag("verify-signatures") {
+        repo.set_verify_signatures(true);
+    }
+
+    if matches.get_flag("continue") {
+        return update_continue(&repo);
+    }
+    if matches.get_flag("abort") {
+        return update_abort(&repo);
+    }
+    if UpdatePlan::exists(&repo) {
+        return Err(anyhow!(
+            "an update is in progress, finish it with `giddy update --continue` or `giddy update --abort`"
+        ));
+    }
+
+    let current_branch = repo.branch_current()?;
+    let branch_names: Vec<String> = match matches.get_many::<String>("branch") {
+        Some(branches) => branches
+            .map(|branch| repo.resolve_branch(branch))
+            .collect::<Result<_>>()?,
+        None => vec![current_branch.name().clone()],
+    };
+
+    let mut order = Vec::new();
+    if all {
+        let default_branch = repo.default_branch_name();
+        order = repo.graph()?.stacked_branches(&default_branch)?;
+    } else if let Some(onto) = onto {
+        let onto = &repo.resolve_branch(onto)?;
+        if branch_names.len() > 1 {
+            return Err(anyhow!("`--onto` can only be used with a single branch"));
+        }
+
+        let name = &branch_names[0];
+        let graph = repo.graph()?;
+        graph.branch_id(onto)?;
+
+        let descendants = graph.get_descendants(name)?;
+        if onto == name || descendants.contains(onto) {
+            return Err(giddy::Error::CycleDetected {
+                branch: name.clone(),
+                dep: onto.clone(),
+            }
+            .into());
+        }
+
+        order.push(name.clone());
+        order.extend(descendants);
+    } else if recursive {
+        use petgraph::visit::DfsPostOrder;
+
+        let graph = repo.graph()?;
+
+        for branch_name in &branch_names {
+            let mut dfs = DfsPostOrder::new(&graph.graph, *graph.branch_id(branch_name)?);
+            while let Some(nx) = dfs.next(&graph.graph) {
+                let branch_name = &graph.graph[nx];
+                if !order.contains(branch_name) {
+                    order.push(branch_name.clone());
+                }
+            }
+        }
+    } else {
+        order = branch_names.clone();
+    }
+
+    if matches.get_flag("with-dependents") {
+        let graph = repo.graph()?;
+        let mut wanted: HashSet<String> = order.iter().cloned().collect();
+        for name in &order {
+            wanted.extend(graph.get_descendants(name)?);
+        }
+
+        // bring everything into dependency order
+        let default_branch = repo.default_branch_name();
+        let sorted = std::iter::once(default_branch.clone())
+            .chain(graph.get_descendants(&default_branch)?)
+            .collect_vec();
+        order.retain(|name| !sorted.contains(name));
+        order.extend(sorted.into_iter().filter(|name| wanted.contains(name)));
+    }
+
+    if matches.get_flag("check") {
+        let mut stale = Vec::new();
+        for name in &order {
+            let branch = Branch::new(name, &repo)?;
+            if !branch.state.planned && branch.needs_update()? {
+                stale.push(name.clone());
+            }
+        }
+        if !stale.is_empty() {
+            return Err(giddy::Error::NeedsUpdate(stale).into());
+        }
+        info!("giddy: all branches are up to date");
+        return Ok(());
+    }
+
+    // up-to-date branches don't get checked out
+    let mut rebasing = onto.is_some();
+    for name in &order {
+        if rebasing {
+            break;
+        }
+        let branch = Branch::new(name, &repo)?;
+        rebasing = !branch.state.planned && branch.needs_update()?;
+    }
+    let autostash = matches.get_flag("autostash") || repo.config().autostash;
+    let autostash = rebasing && check_worktree_clean(&repo, autostash)?;
+
+    // rebasing there would stop halfway through the update
+    for name in &order {
+        if let Some(worktree) = repo.other_worktree(name)? {
+            if repo.rebase_in_progress_at(&worktree)? {
+                return Err(anyhow!(
+                    "branch `{name}` is being rebased in worktree `{worktree}`, finish that first"
+                ));
+            }
+            if repo.merge_in_progress_at(&worktree)? {
+                return Err(anyhow!(
+                    "branch `{name}` is being merged in worktree `{worktree}`, finish that first"
+                ));
+            }
+            if !repo.worktree_is_clean(&worktree)? {
+                return Err(anyhow!(
+                    "branch `{name}` is checked out in worktree `{worktree}`, which has uncommitted changes; commit or stash them there first"
+                ));
+            }
+        }
+    }
+
+    let mut plan = UpdatePlan {
+        checked_out: current_branch.name().clone(),
+        push: matches.get_flag("push") || repo.config().auto_push,
+        range_diff: matches.get_flag("range-diff") || repo.config().post_range_diff,
+        skip_up_to_date: all,
+        ..Default::default()
+    };
+    for name in &order {
+        let mut branch = Branch::new(name, &repo)?;
+        plan.original.insert(
+            name.clone(),
+            Snapshot {
+                head: branch.head()?,
+                state: branch.has_state().then(|| branch.state.clone()),
+            },
+        );
+        // pin base commits before anything moves, so they still match later on
+        if branch.state.base_commit.is_some() {
+            branch.save_state()?;
+        }
+    }
+    plan.remaining = order;
+
+    let jobs = matches.get_one::<usize>("jobs").copied().unwrap_or(1);
+    if jobs > 1 && !git::is_dry_run() {
+        plan.save(&repo)?;
+        let restacked = parallel::restack(&repo, &plan.remaining, jobs)?;
+        repo.refs_changed();
+        for e in &restacked.failed {
+            warning!("{e:#}");
+        }
+        for name in &restacked.rewritten {
+            if plan.push {
+                repo.push_branch(name)?;
+            }
+            plan.rewritten.push(name.clone());
+        }
+        // the failed ones are left to the serial update below
+        plan.updated.extend(restacked.updated);
+    }
+
+    if let Some(onto) = onto {
+        let onto = repo.resolve_branch(onto)?;
+        let mut branch = Branch::new(&branch_names[0], &repo)?;
+        info!(
+            "giddy: setting `{onto}` as only dependency of `{}`",
+            branch.name()
+        );
+        branch.state.deps = IndexSet::from([onto]);
+        branch.save_state()?;
+    }
+
+    if autostash {
+        plan.stash = repo.stash_push()?;
+    }
+    run_update_plan(&repo, plan)
+}
+
+/// Checks for local changes before an update starts, as they would make it
+/// fail at the first rebase. Returns whether to stash them: if `autostash`
+/// is set, or if the user agrees.
+fn check_worktree_clean(repo: &git::Repo, autostash: bool) -> Result<bool> {
+    let status = repo.worktree_status()?;
+    if status.staged + status.unstaged == 0 || git::is_dry_run() {
+        return Ok(false);
+    }
+    if autostash {
+        return Ok(true);
+    }
+
+    let changes = format!(
+        "the working tree has local changes ({} staged, {} unstaged)",
+        status.staged, status.unstaged
+    );
+    if ui::is_interactive() && ui::confirm(&format!("{changes}, stash them during the update?"))? {
+        return Ok(true);
+    }
+    Err(anyhow!(
+        "{changes}, commit or stash them first, or use `--autostash`"
+    ))
+}
+
+/// Updates the remaining branches of `plan`, saving it before each step.
+///
+/// If a rebase stops on a conflict, the plan stays on disk for
+/// `update --continue` / `update --abort`.
+fn run_update_plan(repo: &git::Repo, mut plan: plan::UpdatePlan) -> Result<()> {
+    use git::Branch;
+
+    let options = UpdateOptions {
+        push: plan.push,
+        range_diff: plan.range_diff,
+    };
+    let total = plan.original.len().max(plan.remaining.len());
+    let mut progress = output::Progress::new(total);
+
+    while let Some(name) = plan.remaining.first().cloned() {
+        plan.save(repo)?;
+        progress.step(total - plan.remaining.len() + 1, &name);
+
+        let mut branch = Branch::new(&name, repo)?;
+        branch.save_landed()?;
+        if plan.skip_up_to_date && (branch.state.planned || !branch.needs_update()?) {
+            plan.remaining.remove(0);
+            continue;
+        }
+        progress.keep();
+        match update_branch(repo, &mut branch, &options) {
+            Ok(rewritten) => {
+                if rewritten && !plan.rewritten.contains(&name) {
+                    plan.rewritten.push(name.clone());
+                }
+            }
+            Err(e) if update_stopped(repo, &name)? => {
+                return Err(e.context(giddy::Error::UpdateStopped(name)));
+            }
+            Err(e) => {
+                plan::UpdatePlan::remove(repo)?;
+                if let Err(cleanup) = finish_update(repo, &plan) {
+                    warning!("{cleanup:#}");
+                }
+                return Err(e.context(format!("update stopped at branch `{name}`")));
+            }
+        }
+        plan.remaining.remove(0);
+        plan.updated.push(name);
+    }
+    progress.clear();
+    plan::UpdatePlan::remove(repo)?;
+    if plan.skip_up_to_date && plan.updated.is_empty() {
+        info!("giddy: all branches are up to date");
+    }
+
+    restack_stale_dependents(repo, &plan.rewritten, &plan.updated, &options)?;
+    if options.push && !plan.updated.is_empty() {
+        // reparented branches change the stacks their PRs are listed in
+        if let Err(e) = navigation::refresh(repo, &plan.updated) {
+            warning!("updating stack navigation of PRs failed: {e:#}");
+        }
+    }
+
+    finish_update(repo, &plan)
+}
+
+/// Switches back to the branch the update started on, and restores the
+/// local changes stashed for it.
+fn finish_update(repo: &git::Repo, plan: &plan::UpdatePlan) -> Result<()> {
+    // `git rebase <upstream> <branch>` leaves `<branch>` checked out
+    if !plan.checked_out.is_empty() && repo.branch_current()?.name() != &plan.checked_out {
+        repo.switch(&plan.checked_out)?;
+    }
+    if let Some(stash) = &plan.stash {
+        repo.stash_pop(stash)?;
+    }
+
+    Ok(())
+}
+
+/// Returns whether updating `branch` stopped on a conflict, here or in the
+/// worktree that has it checked out.
+fn update_stopped(repo: &git::Repo, branch: &str) -> Result<bool> {
+    match repo.other_worktree(branch)? {
+        Some(worktree) => {
+            Ok(repo.rebase_in_progress_at(&worktree)? || repo.merge_in_progress_at(&worktree)?)
+        }
+        None => Ok(repo.rebase_in_progress() || repo.merge_in_progress()),
+    }
+}
+
+fn update_continue(repo: &git::Repo) -> Result<()> {
+    use git::Branch;
+
+    let mut plan = plan::UpdatePlan::load(repo)?;
+    let Some(name) = plan.remaining.first().cloned() else {
+        return run_update_plan(repo, plan);
+    };
+
+    let mut merged = false;
+    if let Some(worktree) = repo.other_worktree(&name)? {
+        if repo.rebase_in_progress_at(&worktree)? {
+            return Err(anyhow!(
+                "branch `{name}` is still being rebased in worktree `{worktree}`, finish that first (`git -C {worktree} rebase --continue`)"
+            ));
+        }
+        if repo.merge_in_progress_at(&worktree)? {
+            return Err(anyhow!(
+                "branch `{name}` is still being merged in worktree `{worktree}`, finish that first (`git -C {worktree} commit --no-edit`)"
+            ));
+        }
+        // the merge got committed there, more dependencies might be left
+        merged = Branch::new(&name, repo)?.deps().len() > 1;
+    } else if repo.rebase_in_progress() {
+        repo.cmd_check(["rebase", "--continue"])?.true_or(anyhow!(
+            "rebase of `{name}` is not done yet, resolve the conflicts and try again"
+        ))?;
+    } else if repo.merge_in_progress() {
+        repo.cmd_check(["commit", "--no-edit"])?.true_or(anyhow!(
+            "merge into `{name}` is not done yet, resolve the conflicts and try again"
+        ))?;
+        merged = true;
+    }
+
+    let mut branch = Branch::new(&name, repo)?;
+    if merged {
+        // more dependencies might be left to merge
+        if let Err(e) = branch.update() {
+            if update_stopped(repo, &name)? {
+                return Err(e.context(giddy::Error::UpdateStopped(name)));
+            }
+            return Err(e.context(format!("update stopped at branch `{name}`")));
+        }
+    } else {
+        // the rebase went through, so record it like `update()` would have
+        branch.finish_rebase()?;
+        info!("giddy: finished rebasing branch `{name}`");
+    }
+
+    let options = UpdateOptions {
+        push: plan.push,
+        range_diff: plan.range_diff,
+    };
+    let original = &plan.original[&name];
+    let old_base = original
+        .state
+        .as_ref()
+        .and_then(|state| state.base_commit.clone());
+    if publish_update(repo, &branch, &original.head, old_base, &options)?
+        && !plan.rewritten.contains(&name)
+    {
+        plan.rewritten.push(name.clone());
+    }
+    plan.remaining.remove(0);
+    plan.updated.push(name);
+
+    run_update_plan(repo, plan)
+}
+
+fn update_abort(repo: &git::Repo) -> Result<()> {
+    let plan = plan::UpdatePlan::load(repo)?;
+
+    if let Some(name) = plan.remaining.first() {
+        if let Some(worktree) = repo.other_worktree(name)? {
+            if repo.rebase_in_progress_at(&worktree)? {
+                repo.cmd_check(["-C", worktree.as_str(), "rebase", "--abort"])?
+                    .true_or(anyhow!(
+                        "aborting the rebase in worktree `{worktree}` failed"
+                    ))?;
+            } else if repo.merge_in_progress_at(&worktree)? {
+                repo.cmd_check(["-C", worktree.as_str(), "merge", "--abort"])?
+                    .true_or(anyhow!(
+                        "aborting the merge in worktree `{worktree}` failed"
+                    ))?;
+            }
+        } else if repo.rebase_in_progress() {
+            repo.cmd_check(["rebase", "--abort"])?
+                .true_or(anyhow!("aborting the rebase failed"))?;
+        } else if repo.merge_in_progress() {
+            repo.cmd_check(["merge", "--abort"])?
+                .true_or(anyhow!("aborting the merge failed"))?;
+        }
+    }
+
+    // branches after the stopped one haven't been touched
+    let touched = plan.updated.iter().chain(plan.remaining.first());
+    for name in touched {
+        let snapshot = &plan.original[name];
+        if repo.branch_head(name)? != snapshot.head {
+            repo.reset_branch(name, &snapshot.head)?;
+            info!("giddy: reset branch `{name}` to {}", snapshot.head);
+        }
+
+        repo.set_state_of(name, snapshot.state.as_ref())?;
+    }
+    plan::UpdatePlan::remove(repo)?;
+
+    finish_update(repo, &plan)
+}
+
+/// What to do after `update` rewrote a branch.
+#[derive(Default)]
+struct UpdateOptions {
+    /// force-push the branch
+    push: bool,
+    /// after pushing, post a range-diff to the branch's open PR
+    range_diff: bool,
+}
+
+/// Updates `branch`, returning whether it got rewritten.
+fn update_branch(
+    repo: &git::Repo,
+    branch: &mut git::Branch,
+    options: &UpdateOptions,
+) -> Result<bool> {
+    let old_head = branch.head()?;
+    let old_base = branch.state.base_commit.clone();
+
+    branch.update()?;
+
+    publish_update(repo, branch, &old_head, old_base, options)
+}
+
+/// Pushes `branch` if an update moved it away from `old_head`, and posts the
+/// range-diff. Returns whether the branch got rewritten.
+fn publish_update(
+    repo: &git::Repo,
+    branch: &git::Branch,
+    old_head: &str,
+    old_base: Option<String>,
+    options: &UpdateOptions,
+) -> Result<bool> {
+    let new_head = branch.head()?;
+    if new_head == old_head {
+        return Ok(false);
+    }
+    if !options.push {
+        return Ok(true);
+    }
+
+    repo.push_branch(branch.name())?;
+
+    if !options.range_diff {
+        return Ok(true);
+    }
+    let (Some(pr), Some(old_base), Some(new_base)) =
+        (branch.state.pr, old_base, branch.state.base_commit.clone())
+    else {
+        return Ok(true);
+    };
+    let forge = repo.forge()?;
+    if forge.pr_state(pr)? != "OPEN" {
+        return Ok(true);
+    }
+
+    let range_diff = repo.range_diff(
+        &format!("{old_base}..{old_head}"),
+        &format!("{new_base}..{new_head}"),
+    )?;
+    let body = format!(
+        "Restacked `{}` ({} → {}).\n\n<details><summary>range-diff</summary>\n\n```\n{range_diff}\n```\n</details>\n",
+        branch.name(),
+        &old_head[..old_head.len().min(12)],
+        &new_head[..new_head.len().min(12)],
+    );
+    forge.pr_comment(pr, &body)?;
+    info!("giddy: posted range-diff to PR #{pr}");
+
+    Ok(true)
+}
+
+/// Lists the dependents of the `rewritten` branches that are stale now, and
+/// offers to restack them. Branches in `updated` were already taken care of.
+fn restack_stale_dependents(
+    repo: &git::Repo,
+    rewritten: &[String],
+    updated: &[String],
+    options: &UpdateOptions,
+) -> Result<()> {
+    use git::Branch;
+
+    if rewritten.is_empty() {
+        return Ok(());
+    }
+
+    let graph = repo.graph()?;
+    let mut stale = Vec::new();
+    for name in rewritten {
+        for dependent in graph.get_descendants(name)? {
+            if updated.contains(&dependent) || stale.contains(&dependent) {
+                continue;
+            }
+            // descendants come in dependency order, so stale deps are known by now
+            let has_stale_dep = graph
+                .get_dependencies(&dependent)?
+                .iter()
+                .any(|dep| stale.contains(dep));
+            if has_stale_dep || Branch::new(&dependent, repo)?.needs_update()? {
+                stale.push(dependent);
+            }
+        }
+    }
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    info!(
+        "giddy: these branches are stale now: {}",
+        stale.iter().map(|name| format!("`{name}`")).join(", ")
+    );
+    if !ui::is_interactive() || !ui::confirm("restack them now?")? {
+        info!(
+            "giddy: run `giddy update {}` to restack them",
+            stale.join(" ")
+        );
+        return Ok(());
+    }
+
+    // restack in dependency order
+    let order = graph.get_stack(repo.default_branch_name())?;
+    for name in order.iter().filter(|name| stale.contains(name)) {
+        let mut branch = Branch::new(name, repo)?;
+        update_branch(repo, &mut branch, options)
+            .with_context(|| format!("restacking stopped at branch `{name}`"))?;
+    }
+
+    Ok(())
+}
+
+fn main() {
+    // like git, exit quietly when whoever reads the output stops doing so
+    // (e.g., `gd show -a | head`), instead of panicking in `println!()`
+    // SAFETY: called before any other thread exists
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+
     let result = run();
     match result {
         Err(e) => {
             eprintln!("giddy: error: {e:#}");
-            std::process::exit(1);
+            let code = e
+                .downcast_ref::<giddy::Error>()
+                .map_or(1, giddy::Error::exit_code);
+            std::process::exit(code);
         }
         Ok(code) => std::process::exit(code),
     };
@@ -0,0 +1,181 @@
+//! Names for new branches that were not given one, from the
+//! `branch-name-template` config setting.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Result};
+
+use crate::git::{Repo, TrueOr};
+
+/// Used without a `branch-name-template`.
+const DEFAULT_TEMPLATE: &str = "{parent}-{hex}";
+
+/// Turns `text` into something usable in a branch name, e.g., `Fix the
+/// thing!` into `fix-the-thing`.
+fn slugify(text: &str, max_len: usize) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= max_len {
+            break;
+        }
+    }
+
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Returns today's date (UTC) as `YYYY-MM-DD`.
+fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() / 86400) as i64;
+
+    date_of_day(days)
+}
+
+/// Returns the date `days` after 1970-01-01 as `YYYY-MM-DD`.
+fn date_of_day(days: i64) -> String {
+    // after Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Returns the user part of the branch name: the local part of
+/// `user.email`, or the login name.
+fn user(repo: &Repo) -> String {
+    let email = repo
+        .cmd_output(["config", "user.email"])
+        .unwrap_or_default();
+    let user = match email.trim().split_once('@') {
+        Some((local, _)) if !local.is_empty() => local.to_string(),
+        _ => std::env::var("USER").unwrap_or_default(),
+    };
+
+    slugify(&user, 40)
+}
+
+/// Expands the placeholders of `template`, using `seq` for `{seq}`.
+fn expand(template: &str, values: &[(&str, &str)], seq: usize) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("unclosed `{{` in branch name template `{template}`"))?;
+        let placeholder = &rest[start + 1..start + end];
+        match values.iter().find(|(key, _)| *key == placeholder) {
+            Some((_, value)) => name.push_str(value),
+            None if placeholder == "seq" => name.push_str(&seq.to_string()),
+            None => {
+                return Err(anyhow!(
+                    "unknown placeholder `{{{placeholder}}}` in branch name template `{template}`"
+                ))
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    name.push_str(rest);
+
+    Ok(name)
+}
+
+/// Returns a name for a new branch on `parent`, from the configured
+/// `branch-name-template`.
+///
+/// Placeholders are `{user}`, `{parent}`, `{date}`, `{slug}` (of `message`,
+/// the new branch's first commit message; random without one), `{seq}` (the
+/// lowest number that gives an unused name) and `{hex}` (random).
+pub fn new_branch_name(repo: &Repo, parent: &str, message: Option<&str>) -> Result<String> {
+    let template = repo
+        .config()
+        .branch_name_template
+        .as_deref()
+        .unwrap_or(DEFAULT_TEMPLATE);
+
+    let hex = format!("{:x}", rand::random::<u64>());
+    let slug = message
+        .map(|message| slugify(message.lines().next().unwrap_or_default(), 40))
+        .filter(|slug| !slug.is_empty())
+        .unwrap_or_else(|| hex.clone());
+    let (user, date) = (user(repo), today());
+    let values = [
+        ("user", user.as_str()),
+        ("parent", parent),
+        ("date", date.as_str()),
+        ("slug", slug.as_str()),
+        ("hex", hex.as_str()),
+    ];
+
+    let existing = repo.branch_names()?;
+    let mut name = expand(template, &values, 1)?;
+    if template.contains("{seq}") {
+        for seq in 2.. {
+            if !existing.contains(&name) {
+                break;
+            }
+            name = expand(template, &values, seq)?;
+        }
+    }
+
+    let checked = repo.cmd_output(["check-ref-format", "--branch", &name])?;
+    (checked.trim() == name).true_or(anyhow!(
+        "branch name template `{template}` gives invalid branch name `{name}`"
+    ))?;
+
+    Ok(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_of_days() {
+        assert_eq!(date_of_day(0), "1970-01-01");
+        assert_eq!(date_of_day(-1), "1969-12-31");
+        assert_eq!(date_of_day(59), "1970-03-01");
+        assert_eq!(date_of_day(11016), "2000-02-29");
+        assert_eq!(date_of_day(19782), "2024-02-29");
+        // 2100 is no leap year
+        assert_eq!(date_of_day(47540), "2100-02-28");
+        assert_eq!(date_of_day(47541), "2100-03-01");
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let values = [("user", "me"), ("parent", "main")];
+        assert_eq!(expand("{user}/{parent}", &values, 1).unwrap(), "me/main");
+        assert_eq!(expand("{parent}-{seq}", &values, 3).unwrap(), "main-3");
+        assert_eq!(expand("plain", &values, 1).unwrap(), "plain");
+    }
+
+    #[test]
+    fn expand_errors() {
+        let values = [("user", "me")];
+        let unknown = expand("{user}/{nope}", &values, 1).unwrap_err();
+        assert!(unknown.to_string().contains("unknown placeholder `{nope}`"));
+        let unclosed = expand("{user}/{parent", &values, 1).unwrap_err();
+        assert!(unclosed.to_string().contains("unclosed `{`"));
+    }
+
+    #[test]
+    fn slugify_text() {
+        assert_eq!(slugify("Fix the thing!", 40), "fix-the-thing");
+        assert_eq!(slugify("  --a  b--", 40), "a-b");
+        assert_eq!(slugify("abc def", 5), "abc-d");
+    }
+}
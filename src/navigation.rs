@@ -0,0 +1,185 @@
+//! The section giddy keeps in PR descriptions to navigate between the PRs of
+//! a stack.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::{
+    git::{Branch, Repo},
+    graph::GraphRepo,
+    output::info,
+};
+
+const START: &str = "<!-- giddy:stack -->";
+const END: &str = "<!-- /giddy:stack -->";
+
+/// Returns the PR numbers of `names`, skipping branches without a PR.
+fn prs(repo: &Repo, names: &[String]) -> Result<Vec<u32>> {
+    let mut prs = Vec::new();
+    for name in names {
+        if let Some(pr) = Branch::new(name, repo)?.state.pr {
+            prs.push(pr);
+        }
+    }
+
+    Ok(prs)
+}
+
+fn pr_list(prs: &[u32]) -> String {
+    prs.iter().sorted().map(|pr| format!("#{pr}")).join(", ")
+}
+
+/// Returns the navigation section for PR `pr` of `branch`, or `None` if it
+/// is the only PR of its stack.
+fn section(repo: &Repo, graph: &GraphRepo, branch: &str, pr: u32) -> Result<Option<String>> {
+    let stack = prs(repo, &graph.get_stack(branch)?)?;
+    if stack.len() < 2 {
+        return Ok(None);
+    }
+
+    let mut lines = vec![START.to_string(), "**Stack**".to_string(), String::new()];
+    for other in stack {
+        lines.push(if other == pr {
+            format!("- **#{other}** ⬅ this PR")
+        } else {
+            format!("- #{other}")
+        });
+    }
+
+    let depends_on = prs(repo, &graph.get_dependencies(branch)?)?;
+    let needed_by = prs(repo, &graph.get_dependents(branch)?)?;
+    let mut relations = Vec::new();
+    if !depends_on.is_empty() {
+        relations.push(format!("⬆ depends on {}", pr_list(&depends_on)));
+    }
+    if !needed_by.is_empty() {
+        relations.push(format!("⬇ needed by {}", pr_list(&needed_by)));
+    }
+    if !relations.is_empty() {
+        lines.push(String::new());
+        lines.push(relations.join(", "));
+    }
+    lines.push(END.to_string());
+
+    Ok(Some(lines.join("\n")))
+}
+
+/// Returns `body` with its navigation section replaced by `section` (or
+/// removed, if `None`). A new section goes at the end.
+fn with_section(body: &str, section: Option<&str>) -> String {
+    // the last start marker before an end marker, so a stray one (e.g., cut
+    // from a copied section) doesn't swallow the text after it
+    let existing = body
+        .find(END)
+        .and_then(|end| Some((body[..end].rfind(START)?, end + END.len())));
+
+    match (existing, section) {
+        (Some((start, end)), Some(section)) => {
+            format!("{}{section}{}", &body[..start], &body[end..])
+        }
+        (Some((start, end)), None) => [body[..start].trim_end(), body[end..].trim_start()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .join("\n\n"),
+        (None, Some(section)) if body.trim().is_empty() => section.to_string(),
+        (None, Some(section)) => format!("{}\n\n{section}", body.trim_end()),
+        (None, None) => body.to_string(),
+    }
+}
+
+/// Brings the navigation section of every PR in the stacks of `branches` up
+/// to date, listing the stack's PRs and what the PR depends on.
+///
+/// Descriptions are only edited if the section changed.
+pub fn refresh(repo: &Repo, branches: &[String]) -> Result<()> {
+    let graph = repo.graph()?;
+    let mut seen = HashSet::new();
+    let mut prs = Vec::new();
+    for name in branches {
+        for member in graph.get_stack(name)? {
+            if !seen.insert(member.clone()) {
+                continue;
+            }
+            if let Some(pr) = Branch::new(&member, repo)?.state.pr {
+                prs.push((member, pr));
+            }
+        }
+    }
+    if prs.is_empty() {
+        return Ok(());
+    }
+
+    let forge = repo.forge()?;
+    for (name, pr) in prs {
+        let section = section(repo, &graph, &name, pr)?;
+        let body = forge.pr_body(pr)?;
+        let new_body = with_section(&body, section.as_deref());
+        if new_body != body {
+            forge.set_pr_body(pr, &new_body)?;
+            info!("giddy: updated stack navigation of PR #{pr}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section() -> String {
+        format!("{START}\n**Stack**\n\n- #1\n- **#2** ⬅ this PR\n{END}")
+    }
+
+    #[test]
+    fn appends_a_new_section() {
+        let section = section();
+        assert_eq!(
+            with_section("Fixes things.\n", Some(&section)),
+            format!("Fixes things.\n\n{section}")
+        );
+    }
+
+    #[test]
+    fn empty_body_gets_only_the_section() {
+        let section = section();
+        assert_eq!(with_section("", Some(&section)), section);
+        assert_eq!(with_section(" \n", Some(&section)), section);
+    }
+
+    #[test]
+    fn replaces_the_section() {
+        let old = format!("{START}\nold\n{END}");
+        let body = format!("Before.\n\n{old}\n\nAfter.");
+        let section = section();
+        assert_eq!(
+            with_section(&body, Some(&section)),
+            format!("Before.\n\n{section}\n\nAfter.")
+        );
+        // unchanged sections leave the body as is
+        let body = format!("Before.\n\n{section}");
+        assert_eq!(with_section(&body, Some(&section)), body);
+    }
+
+    #[test]
+    fn removes_the_section() {
+        let section = section();
+        let body = format!("Before.\n\n{section}\n\nAfter.");
+        assert_eq!(with_section(&body, None), "Before.\n\nAfter.");
+        assert_eq!(with_section(&section, None), "");
+        assert_eq!(with_section("Just text.", None), "Just text.");
+    }
+
+    #[test]
+    fn keeps_a_start_marker_without_end() {
+        let body = format!("Before.\n{START}\nAfter.");
+        let section = section();
+        let new_body = with_section(&body, Some(&section));
+        assert_eq!(new_body, format!("{body}\n\n{section}"));
+        // and doesn't touch it when replacing the section later
+        assert_eq!(with_section(&new_body, Some(&section)), new_body);
+        assert_eq!(with_section(&body, None), body);
+    }
+}
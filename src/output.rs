@@ -0,0 +1,127 @@
+//! Messages giddy prints about what it is doing, as opposed to the output a
+//! command was asked for (which goes straight to stdout).
+
+use std::{
+    io::{IsTerminal, Write},
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    time::Instant,
+};
+
+static QUIET: AtomicU8 = AtomicU8::new(0);
+static PORCELAIN: AtomicBool = AtomicBool::new(false);
+
+/// Silences informational messages (`1`), and also warnings (`2` and up).
+/// Errors are always printed.
+pub fn set_quiet(level: u8) {
+    QUIET.store(level, Ordering::Relaxed);
+}
+
+pub fn quiet_level() -> u8 {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Keeps stdout for `--porcelain` records, moving informational messages to
+/// stderr.
+pub fn set_porcelain() {
+    PORCELAIN.store(true, Ordering::Relaxed);
+}
+
+pub fn is_porcelain() -> bool {
+    PORCELAIN.load(Ordering::Relaxed)
+}
+
+/// Prints an informational message to stdout (stderr with `--porcelain`),
+/// unless `--quiet` was given.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::output::quiet_level() == 0 {
+            if $crate::output::is_porcelain() {
+                eprintln!($($arg)*);
+            } else {
+                println!($($arg)*);
+            }
+        }
+    };
+}
+
+/// Prints `giddy: warning: <message>` to stderr, unless `-qq` was given.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::output::quiet_level() < 2 {
+            eprintln!("giddy: warning: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+pub use crate::{info, warning};
+
+/// Reports how far a multi-branch operation got.
+///
+/// On a terminal, the line of a branch that turns out to need nothing is
+/// overwritten by the next one; otherwise each step gets a plain line.
+pub struct Progress {
+    total: usize,
+    start: Instant,
+    tty: bool,
+    /// a step was printed without finishing its line
+    pending: bool,
+}
+
+impl Progress {
+    /// Starts reporting on `total` steps; stays silent for single steps or
+    /// when quiet.
+    pub fn new(total: usize) -> Self {
+        Self {
+            total: if quiet_level() > 0 || total < 2 {
+                0
+            } else {
+                total
+            },
+            start: Instant::now(),
+            tty: std::io::stdout().is_terminal(),
+            pending: false,
+        }
+    }
+
+    /// Announces that step `position` (counting from 1) is about `branch`.
+    pub fn step(&mut self, position: usize, branch: &str) {
+        if self.total == 0 {
+            return;
+        }
+        let line = format!(
+            "giddy: [{position}/{}] `{branch}` ({}s)",
+            self.total,
+            self.start.elapsed().as_secs()
+        );
+        if self.tty {
+            print!("\r\x1b[K{line}");
+            std::io::stdout().flush().ok();
+            self.pending = true;
+        } else {
+            println!("{line}");
+        }
+    }
+
+    /// Keeps the current step's line, as the step is about to print more.
+    pub fn keep(&mut self) {
+        if std::mem::take(&mut self.pending) {
+            println!();
+        }
+    }
+
+    /// Removes the line of the current step, if it is still unfinished.
+    pub fn clear(&mut self) {
+        if std::mem::take(&mut self.pending) {
+            print!("\r\x1b[K");
+            std::io::stdout().flush().ok();
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    io::{IsTerminal, Write},
+    os::fd::{AsFd, AsRawFd, OwnedFd},
+    process::{Child, Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{git::GitCommand, ui};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+/// A pager process that stdout is redirected to, for as long as this lives.
+pub struct Pager {
+    child: Child,
+    stdout: OwnedFd,
+}
+
+/// Returns the pager command, like git picks it.
+fn pager_command() -> Option<String> {
+    let pager = std::env::var("GIT_PAGER")
+        .ok()
+        .or_else(|| {
+            let output = GitCommand::new()
+                .args(["config", "core.pager"])
+                .output()
+                .ok()?;
+            let pager = String::from_utf8(output.stdout).ok()?;
+            let pager = pager.trim();
+            (!pager.is_empty()).then(|| pager.to_string())
+        })
+        .or_else(|| std::env::var("PAGER").ok())
+        .unwrap_or_else(|| String::from("less"));
+
+    (!pager.is_empty() && pager != "cat").then_some(pager)
+}
+
+/// Pipes stdout through the user's pager, if stdout is a terminal.
+pub fn start() -> Option<Pager> {
+    if DISABLED.load(Ordering::Relaxed) || !ui::is_interactive() || !std::io::stdout().is_terminal()
+    {
+        return None;
+    }
+
+    let pager = pager_command()?;
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&pager).stdin(Stdio::piped());
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let mut child = command.spawn().ok()?;
+    let pipe = OwnedFd::from(child.stdin.take()?);
+
+    let stdout = std::io::stdout().as_fd().try_clone_to_owned().ok()?;
+    // SAFETY: both are valid, open file descriptors
+    if unsafe { libc::dup2(pipe.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+        return None;
+    }
+
+    Some(Pager { child, stdout })
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        std::io::stdout().flush().ok();
+        // restoring stdout closes the last write end of the pipe, so the pager sees EOF
+        // SAFETY: both are valid, open file descriptors
+        unsafe { libc::dup2(self.stdout.as_raw_fd(), libc::STDOUT_FILENO) };
+        self.child.wait().ok();
+    }
+}
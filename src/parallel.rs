@@ -0,0 +1,231 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{anyhow, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    git::{Branch, Repo, TrueOr},
+    output::{info, warning},
+};
+
+/// Outcome of restacking branches side by side.
+#[derive(Debug, Default)]
+pub struct Restacked {
+    /// branches that got updated, in the order they were done
+    pub updated: Vec<String>,
+    /// the subset of `updated` whose head changed
+    pub rewritten: Vec<String>,
+    /// groups that stopped, with the error of the branch they stopped at
+    pub failed: Vec<anyhow::Error>,
+}
+
+/// Splits `order` into groups of branches that are connected through
+/// dependencies other than the default branch, keeping them in `order`.
+///
+/// Branches of different groups can be rebased independently of each other.
+fn independent_groups(repo: &Repo, order: &[String]) -> Result<Vec<Vec<String>>> {
+    let default_branch = repo.default_branch_name();
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    let mut groups: Vec<Vec<String>> = Vec::new();
+
+    for name in order {
+        let branch = Branch::new(name, repo)?;
+        let mut joined = branch
+            .deps()
+            .iter()
+            .filter(|dep| **dep != default_branch)
+            .filter_map(|dep| group_of.get(dep.as_str()).copied())
+            .collect::<Vec<_>>();
+        joined.sort_unstable();
+        joined.dedup();
+
+        let Some((&target, others)) = joined.split_first() else {
+            group_of.insert(name.clone(), groups.len());
+            groups.push(vec![name.clone()]);
+            continue;
+        };
+        for &other in others {
+            for member in std::mem::take(&mut groups[other]) {
+                group_of.insert(member.clone(), target);
+                groups[target].push(member);
+            }
+        }
+        group_of.insert(name.clone(), target);
+        groups[target].push(name.clone());
+    }
+
+    let position: HashMap<&String, usize> = order.iter().enumerate().map(|(i, n)| (n, i)).collect();
+    groups.retain(|group| !group.is_empty());
+    for group in &mut groups {
+        group.sort_by_key(|name| position[name]);
+    }
+
+    Ok(groups)
+}
+
+/// Returns whether the branches of `group` can be rebased in a temporary
+/// worktree, and one of them needs it.
+///
+/// Branches that are checked out somewhere, planned, or merge several
+/// dependencies are left to the regular, serial update.
+fn is_eligible(repo: &Repo, current: &str, group: &[String]) -> Result<bool> {
+    let mut needs_update = false;
+    for name in group {
+        let branch = Branch::new(name, repo)?;
+        if name == current
+            || branch.state.planned
+            || branch.deps().len() > 1
+            || repo.other_worktree(name)?.is_some()
+        {
+            return Ok(false);
+        }
+        needs_update = needs_update || branch.needs_update()?;
+    }
+
+    Ok(needs_update)
+}
+
+/// Rebases the independent groups of `order` (as returned by
+/// `GraphRepo::get_descendants()`) side by side, using up to `jobs`
+/// temporary worktrees.
+///
+/// Within a group, branches are updated one after another in `order`. A
+/// group stops at the first branch that fails, with its rebase aborted, so
+/// the serial update can take over from there.
+pub fn restack(repo: &Repo, order: &[String], jobs: usize) -> Result<Restacked> {
+    let current = repo
+        .branch_current()
+        .map(|branch| branch.name().clone())
+        .unwrap_or_default();
+
+    let mut queue = Vec::new();
+    for group in independent_groups(repo, order)? {
+        if is_eligible(repo, &current, &group)? {
+            queue.push(group);
+        }
+    }
+    if queue.is_empty() {
+        return Ok(Restacked::default());
+    }
+
+    let jobs = jobs.min(queue.len());
+    info!(
+        "giddy: restacking {} independent stacks using {jobs} worktrees...",
+        queue.len()
+    );
+    // workers take groups from the back
+    queue.reverse();
+
+    let queue = Mutex::new(queue);
+    let result = Mutex::new(Restacked::default());
+    std::thread::scope(|scope| {
+        for job in 0..jobs {
+            let worktree = repo
+                .git_dir()
+                .join("giddy")
+                .join(format!(".worktree-{job}"));
+            let (queue, result) = (&queue, &result);
+            scope.spawn(move || {
+                if let Err(e) = worker(&worktree, queue, result) {
+                    result.lock().unwrap().failed.push(e);
+                }
+            });
+        }
+    });
+
+    Ok(result.into_inner().unwrap())
+}
+
+/// Restacks groups from `queue` in a temporary worktree at `path` until
+/// none are left.
+fn worker(
+    path: &Utf8Path,
+    queue: &Mutex<Vec<Vec<String>>>,
+    result: &Mutex<Restacked>,
+) -> Result<()> {
+    // `Repo` is not `Sync`, so each worker has its own
+    let repo = Repo::open()?;
+    let worktree = TempWorktree::add(&repo, path)?;
+
+    while let Some(group) = queue.lock().unwrap().pop() {
+        for name in &group {
+            match restack_branch(&worktree.path, name) {
+                Ok(Some(rewritten)) => {
+                    let mut result = result.lock().unwrap();
+                    result.updated.push(name.clone());
+                    if rewritten {
+                        result.rewritten.push(name.clone());
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    result.lock().unwrap().failed.push(e);
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Updates branch `name` in the worktree at `path`, returning whether its
+/// head changed, or `None` if it was up to date.
+fn restack_branch(path: &Utf8Path, name: &str) -> Result<Option<bool>> {
+    // the worktree rebase bypasses the caches, so start fresh for each branch
+    let repo = Repo::open()?;
+    let mut branch = Branch::new(name, &repo)?;
+    if !branch.needs_update()? {
+        return Ok(None);
+    }
+
+    let old_head = branch.head()?;
+    repo.cmd_check(["-C", path.as_str(), "switch", "--quiet", name])?
+        .true_or(anyhow!("checking out `{name}` in worktree `{path}` failed"))?;
+
+    let updated = branch.update();
+    if updated.is_err() && repo.rebase_in_progress_at(path)? {
+        repo.cmd_check(["-C", path.as_str(), "rebase", "--abort"])?;
+    }
+    repo.cmd_check(["-C", path.as_str(), "switch", "--quiet", "--detach"])?;
+    updated?;
+
+    Ok(Some(branch.head()? != old_head))
+}
+
+/// A detached worktree that is removed again when dropped.
+struct TempWorktree<'a> {
+    repo: &'a Repo,
+    path: Utf8PathBuf,
+}
+
+impl<'a> TempWorktree<'a> {
+    fn add(repo: &'a Repo, path: &Utf8Path) -> Result<Self> {
+        // left over from an interrupted run
+        if path.exists() {
+            repo.cmd_check(["worktree", "remove", "--force", path.as_str()])?;
+        }
+        repo.cmd_check(["worktree", "add", "--quiet", "--detach", path.as_str()])?
+            .true_or(anyhow!("creating worktree `{path}` failed"))?;
+
+        Ok(Self {
+            repo,
+            path: path.to_owned(),
+        })
+    }
+}
+
+impl Drop for TempWorktree<'_> {
+    fn drop(&mut self) {
+        if !self
+            .repo
+            .cmd_check(["worktree", "remove", "--force", self.path.as_str()])
+            .unwrap_or(false)
+        {
+            warning!(
+                "removing worktree `{}` failed, remove it with `git worktree remove --force`",
+                self.path
+            );
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::{anyhow, Context, Result};
+use camino::Utf8PathBuf;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::git::{is_dry_run, read_from_file, write_to_file, BranchState, Repo};
+
+/// Head and state of a branch before an update touched it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub head: String,
+    /// `None` if the branch had no state file
+    pub state: Option<BranchState>,
+}
+
+/// The branches an `update` still has to go through.
+///
+/// Gets written to `.git/giddy/.update-plan` before each step, so an update
+/// that stopped on a conflict can be continued or rolled back.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdatePlan {
+    /// branches left to update, in order; the first one is the one in progress
+    pub remaining: Vec<String>,
+    /// branches done so far
+    pub updated: Vec<String>,
+    /// branches whose head changed so far
+    pub rewritten: Vec<String>,
+    /// every planned branch as it was before the update started
+    pub original: IndexMap<String, Snapshot>,
+    /// branch to switch back to when done
+    pub checked_out: String,
+    pub push: bool,
+    pub range_diff: bool,
+    /// leave out branches that are up to date when their turn comes
+    #[serde(default)]
+    pub skip_up_to_date: bool,
+    /// local changes stashed before the update, restored when it is done
+    #[serde(default)]
+    pub stash: Option<String>,
+}
+
+impl UpdatePlan {
+    fn path(repo: &Repo) -> Utf8PathBuf {
+        repo.git_dir().join("giddy").join(".update-plan")
+    }
+
+    pub fn exists(repo: &Repo) -> bool {
+        Self::path(repo).exists()
+    }
+
+    pub fn load(repo: &Repo) -> Result<Self> {
+        let path = Self::path(repo);
+        if !path.exists() {
+            return Err(anyhow!("no update in progress"));
+        }
+        read_from_file(&path).context("reading update plan")
+    }
+
+    pub fn save(&self, repo: &Repo) -> Result<()> {
+        if is_dry_run() {
+            return Ok(());
+        }
+        write_to_file(Self::path(repo), self).context("writing update plan")
+    }
+
+    pub fn remove(repo: &Repo) -> Result<()> {
+        let path = Self::path(repo);
+        if path.exists() {
+            std::fs::remove_file(&path).context("removing update plan")?;
+        }
+        Ok(())
+    }
+}
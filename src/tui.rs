@@ -0,0 +1,316 @@
+use std::{collections::HashSet, io::Write, process::Command};
+
+use anyhow::{anyhow, Context, Result};
+use itertools::Itertools;
+use ratatui::{
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEventKind},
+        execute,
+        terminal::{enable_raw_mode, EnterAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+
+use giddy::{
+    git::{is_dry_run, Branch, Repo},
+    ui,
+};
+
+const HELP: &str =
+    "↑/↓ move  enter switch  u update  r reparent  p open/create PR  g refresh  q quit";
+
+/// One line of the branch tree.
+struct Row {
+    name: String,
+    depth: usize,
+    status: Vec<String>,
+    /// shown again under another dependency, its dependents are listed at the first spot
+    repeated: bool,
+    stale: bool,
+    pr: Option<u32>,
+}
+
+enum Mode {
+    Browse,
+    /// picking the new base for this branch
+    Reparent(String),
+}
+
+struct App {
+    rows: Vec<Row>,
+    list: ListState,
+    current: String,
+    mode: Mode,
+    message: String,
+}
+
+impl App {
+    /// Reads the branch tree, keeping the selected branch selected.
+    fn load(&mut self) -> Result<()> {
+        // commands ran in between, so start over with fresh caches
+        let repo = Repo::open()?;
+        let default_branch = repo.default_branch_name();
+        let graph = repo.graph()?;
+        let selected = self.selected().map(|row| row.name.clone());
+
+        let mut rows = Vec::new();
+        let mut seen = HashSet::new();
+        let mut todo = vec![(default_branch.clone(), 0)];
+        while let Some((name, depth)) = todo.pop() {
+            let branch = Branch::new(&name, &repo)?;
+            let mut status = Vec::new();
+            let mut stale = false;
+            if name == default_branch {
+                status.push("default".into());
+            } else if branch.state.planned {
+                status.push("planned".into());
+            } else {
+                if branch.needs_update()? {
+                    status.push("needs update".into());
+                    stale = true;
+                }
+                if branch.merged_into(&default_branch)? {
+                    status.push("merged".into());
+                }
+            }
+            if branch.state.dirty {
+                status.push("dirty".into());
+            }
+            if let Some(pr) = branch.state.pr {
+                status.push(format!("PR #{pr}"));
+            }
+
+            let repeated = !seen.insert(name.clone());
+            if !repeated {
+                for dependent in graph.get_dependents(&name)?.into_iter().rev() {
+                    todo.push((dependent, depth + 1));
+                }
+            }
+            rows.push(Row {
+                name,
+                depth,
+                status,
+                repeated,
+                stale,
+                pr: branch.state.pr,
+            });
+        }
+
+        self.current = repo
+            .branch_current()
+            .map(|branch| branch.name().clone())
+            .unwrap_or_default();
+        let index = selected
+            .and_then(|selected| rows.iter().position(|row| row.name == selected))
+            .or_else(|| rows.iter().position(|row| row.name == self.current))
+            .unwrap_or_default();
+        self.rows = rows;
+        self.list.select(Some(index));
+
+        Ok(())
+    }
+
+    fn selected(&self) -> Option<&Row> {
+        self.list.selected().and_then(|index| self.rows.get(index))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tree, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(frame.area());
+
+        let items = self.rows.iter().map(|row| {
+            let mut name = Span::raw(&row.name);
+            if row.name == self.current {
+                name = name.bold();
+            }
+            if row.repeated {
+                name = name.dim();
+            }
+            let marker = if row.name == self.current { "* " } else { "  " };
+            let mut spans = vec![
+                Span::raw(format!("{}{marker}", "  ".repeat(row.depth))),
+                name,
+            ];
+            if !row.status.is_empty() {
+                let status = Span::raw(format!("  ({})", row.status.join(", ")));
+                spans.push(if row.stale {
+                    status.fg(Color::Yellow)
+                } else {
+                    status.dim()
+                });
+            }
+            ListItem::new(Line::from(spans))
+        });
+
+        let title = match &self.mode {
+            Mode::Browse => " giddy ".to_string(),
+            Mode::Reparent(name) => {
+                format!(" new base for `{name}` (enter to pick, esc to cancel) ")
+            }
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, tree, &mut self.list);
+
+        frame.render_widget(
+            Paragraph::new(vec![Line::raw(&self.message), Line::raw(HELP).dim()]),
+            footer,
+        );
+    }
+}
+
+/// Runs the full-screen interface until the user quits.
+pub fn run() -> Result<()> {
+    if !ui::is_interactive() {
+        return Err(anyhow!("`giddy tui` needs a terminal"));
+    }
+
+    let mut app = App {
+        rows: Vec::new(),
+        list: ListState::default(),
+        current: String::new(),
+        mode: Mode::Browse,
+        message: String::new(),
+    };
+    app.load()?;
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut app);
+    ratatui::restore();
+
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        app.message.clear();
+        let Some(selected) = app.selected().map(|row| (row.name.clone(), row.pr)) else {
+            if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                return Ok(());
+            }
+            continue;
+        };
+        let (name, pr) = selected;
+
+        match (&app.mode, key.code) {
+            (_, KeyCode::Up | KeyCode::Char('k')) => app.list.select_previous(),
+            (_, KeyCode::Down | KeyCode::Char('j')) => app.list.select_next(),
+            (Mode::Reparent(_), KeyCode::Esc) => app.mode = Mode::Browse,
+            (Mode::Reparent(branch), KeyCode::Enter) => {
+                let branch = branch.clone();
+                app.mode = Mode::Browse;
+                run_giddy(terminal, app, &[&["update", &branch, "--onto", &name]], &[])?;
+            }
+            (Mode::Reparent(_), _) => {}
+            (Mode::Browse, KeyCode::Char('q') | KeyCode::Esc) => return Ok(()),
+            (Mode::Browse, KeyCode::Enter) => {
+                run_giddy(terminal, app, &[&["co", &name]], &[])?;
+            }
+            (Mode::Browse, KeyCode::Char('u')) => {
+                run_giddy(terminal, app, &[&["update", &name]], &[])?;
+            }
+            (Mode::Browse, KeyCode::Char('r')) => {
+                app.message = format!("pick the new base for `{name}`");
+                app.mode = Mode::Reparent(name);
+            }
+            (Mode::Browse, KeyCode::Char('p')) => match pr {
+                Some(pr) => {
+                    app.message = match Repo::open().and_then(|repo| repo.forge()?.open_pr(pr)) {
+                        Ok(()) => format!("opened PR #{pr}"),
+                        Err(e) => format!("error: {e:#}"),
+                    };
+                }
+                None => {
+                    // `pr create` works on the current branch
+                    let current = app.current.clone();
+                    let switch_to = ["co", name.as_str()];
+                    let create = ["pr", "create"];
+                    let switch_back = ["co", current.as_str()];
+                    let mut cleanup: Vec<&[&str]> = Vec::new();
+                    if current != name && !current.is_empty() {
+                        cleanup.push(&switch_back);
+                    }
+                    run_giddy(terminal, app, &[&switch_to, &create], &cleanup)?;
+                }
+            },
+            (Mode::Browse, KeyCode::Char('g')) => {
+                if let Err(e) = app.load() {
+                    app.message = format!("error: {e:#}");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Leaves the full-screen mode to run giddy `commands` one after another,
+/// stopping at the first that fails, then the `cleanup` commands (even after
+/// a failure), then reloads the tree.
+fn run_giddy(
+    terminal: &mut DefaultTerminal,
+    app: &mut App,
+    commands: &[&[&str]],
+    cleanup: &[&[&str]],
+) -> Result<()> {
+    let exe = std::env::current_exe().context("finding the giddy executable")?;
+    let run = |args: &[&str]| -> Result<bool> {
+        println!("$ giddy {}", args.join(" "));
+        let mut giddy = Command::new(&exe);
+        if is_dry_run() {
+            giddy.arg("--dry-run");
+        }
+        Ok(giddy.args(args).status()?.success())
+    };
+
+    ratatui::restore();
+    let mut failed = None;
+    for args in commands {
+        if !run(args)? {
+            failed = Some(args.join(" "));
+            break;
+        }
+    }
+    for args in cleanup {
+        if !run(args)? && failed.is_none() {
+            failed = Some(args.join(" "));
+        }
+    }
+    print!("press enter to return");
+    std::io::stdout().flush()?;
+    std::io::stdin().read_line(&mut String::new())?;
+
+    enable_raw_mode()?;
+    execute!(std::io::stdout(), EnterAlternateScreen)?;
+    terminal.clear()?;
+
+    app.message = match failed {
+        Some(command) => format!("`giddy {command}` failed"),
+        None => format!(
+            "ran {}",
+            commands
+                .iter()
+                .chain(cleanup)
+                .map(|args| format!("`giddy {}`", args.join(" ")))
+                .join(", ")
+        ),
+    };
+    if let Err(e) = app.load() {
+        app.message = format!("error: {e:#}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use dialoguer::{theme::ColorfulTheme, Confirm, FuzzySelect, Input, MultiSelect, Select, Sort};
+
+use crate::Error;
+
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Disables all prompts, for giddy itself and for the git commands it runs.
+pub fn set_non_interactive() {
+    NON_INTERACTIVE.store(true, Ordering::Relaxed);
+
+    // only called at startup, before any threads are spawned
+    for (key, value) in [
+        ("GIT_EDITOR", "true"),
+        ("GIT_SEQUENCE_EDITOR", "true"),
+        ("GIT_MERGE_AUTOEDIT", "no"),
+        ("GIT_PAGER", "cat"),
+        ("PAGER", "cat"),
+        ("GIT_TERMINAL_PROMPT", "0"),
+        ("GIT_ASKPASS", "true"),
+        ("GH_PROMPT_DISABLED", "1"),
+    ] {
+        std::env::set_var(key, value);
+    }
+}
+
+/// Returns whether running in CI, where nobody can answer prompts.
+pub fn is_ci() -> bool {
+    std::env::var("CI").is_ok_and(|ci| ci == "true" || ci == "1")
+}
+
+/// Returns the error used instead of prompting in non-interactive mode.
+fn prompt_error(prompt: &str) -> anyhow::Error {
+    Error::PromptRequired(prompt.to_string()).into()
+}
+
+/// Returns whether we can prompt the user.
+pub fn is_interactive() -> bool {
+    !NON_INTERACTIVE.load(Ordering::Relaxed)
+        && std::io::stdin().is_terminal()
+        && std::io::stderr().is_terminal()
+}
+
+/// Asks the user a yes/no question.
+pub fn confirm(prompt: &str) -> Result<bool> {
+    if !is_interactive() {
+        return Err(prompt_error(prompt));
+    }
+
+    Ok(Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(true)
+        .interact()?)
+}
+
+/// Asks the user for a line of text, offering `default`.
+pub fn input(prompt: &str, default: &str) -> Result<String> {
+    if !is_interactive() {
+        return Err(prompt_error(prompt));
+    }
+
+    Ok(Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .default(default.to_string())
+        .interact_text()?)
+}
+
+/// Lets the user pick one of `items`, returning its index.
+pub fn pick_one(prompt: &str, items: &[String]) -> Result<usize> {
+    if !is_interactive() {
+        return Err(prompt_error(prompt));
+    }
+
+    let picked = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .default(0)
+        .interact()?;
+
+    Ok(picked)
+}
+
+/// Lets the user pick one of `items` by typing parts of it, returning its index.
+pub fn pick_fuzzy(prompt: &str, items: &[String], default: usize) -> Result<usize> {
+    if !is_interactive() {
+        return Err(prompt_error(prompt));
+    }
+
+    let picked = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .default(default)
+        .interact()?;
+
+    Ok(picked)
+}
+
+/// Lets the user rearrange `items`, returning their indices in the new order.
+pub fn sort(prompt: &str, items: &[String]) -> Result<Vec<usize>> {
+    if !is_interactive() {
+        return Err(prompt_error(prompt));
+    }
+
+    Ok(Sort::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .interact()?)
+}
+
+/// Lets the user pick any number of `items`, returning the picked ones.
+///
+/// `defaults` contains the initially selected state of each item.
+pub fn pick_many(prompt: &str, items: &[String], defaults: &[bool]) -> Result<Vec<String>> {
+    if !is_interactive() {
+        return Err(prompt_error(prompt));
+    }
+
+    let picked = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(items)
+        .defaults(defaults)
+        .interact()?;
+
+    Ok(picked
+        .into_iter()
+        .map(|index| items[index].clone())
+        .collect())
+}